@@ -0,0 +1,25 @@
+use chessbot::game::{Game, START_FEN};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const POSITIONS: [(&str, &str); 2] = [
+    ("start", START_FEN),
+    ("kiwipete", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"),
+];
+
+fn perft_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("perft");
+    group.sample_size(10);
+
+    for (name, fen) in POSITIONS {
+        let game = Game::from_fen(fen).expect("Could not parse benchmark FEN");
+
+        for depth in 4..=5 {
+            group.bench_with_input(BenchmarkId::new(name, depth), &depth, |b, &depth| b.iter(|| game.perft(depth)));
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, perft_benchmark);
+criterion_main!(benches);
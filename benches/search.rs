@@ -0,0 +1,30 @@
+use chessbot::engine::Engine;
+use chessbot::game::{Game, START_FEN};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const POSITIONS: [(&str, &str); 2] = [
+    ("start", START_FEN),
+    ("kiwipete", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"),
+];
+
+fn search_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+    group.sample_size(10);
+
+    for (name, fen) in POSITIONS {
+        let game = Game::from_fen(fen).expect("Could not parse benchmark FEN");
+
+        for use_transposition_table in [false, true] {
+            let mut engine = Engine::new(game.clone(), game.turn, 4);
+            engine.use_transposition_table = use_transposition_table;
+
+            let id = if use_transposition_table { "transposition table" } else { "no table" };
+            group.bench_with_input(BenchmarkId::new(name, id), &engine, |b, engine| b.iter(|| engine.get_best_move_parallel()));
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, search_benchmark);
+criterion_main!(benches);
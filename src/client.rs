@@ -1,25 +1,227 @@
+pub mod lichess;
+
 use thirtyfour::prelude::*;
 use thirtyfour::cookie::Cookie;
 use thirtyfour::cookie::SameSite;
 use regex::*;
 use lazy_static::lazy_static;
+use log::{debug, info, warn};
 use crate::game::position::Position;
+use std::fmt;
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use super::game::Game;
 use super::game::board::Board;
 use super::game::piece::{PieceType, PieceColor};
 use super::game::chess_move::ChessMove;
 use super::game::piece::*;
 
+// How long make_move keeps reading the board for its move to show, and how often
+const MOVE_LANDED_TIMEOUT: Duration = Duration::from_secs(2);
+const MOVE_LANDED_POLL: Duration = Duration::from_millis(50);
+
+/// Shared flag asking the bot's loops to stop, set from the Ctrl-C handler
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        Shutdown::default()
+    }
+
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
+
+/// Where ChromeDriver listens by default
+pub const DEFAULT_WEBDRIVER_URL: &str = "http://localhost:9515";
+
+// Headless browsers default to a small window, which chess.com lays out without the full board
+const HEADLESS_WINDOW_SIZE: (u32, u32) = (1920, 1080);
+
+/// Browser the WebDriver session drives
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Browser {
+    Chrome,
+    Firefox,
+}
+
+/// How to reach the WebDriver server, which may be a local ChromeDriver or geckodriver
+/// or a remote Selenium grid
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ClientConfig {
+    pub webdriver_url: String,
+    pub browser: Browser,
+    /// Run the browser without a window, for servers and CI. The page is still laid out at a
+    /// desktop size so the board is found and clicked the same way as in a visible window
+    pub headless: bool,
+}
+
+impl Default for ClientConfig {
+    fn default() -> ClientConfig {
+        ClientConfig {
+            webdriver_url: DEFAULT_WEBDRIVER_URL.to_owned(),
+            browser: Browser::Chrome,
+            headless: false,
+        }
+    }
+}
+
+impl ClientConfig {
+    /// The capabilities asking the WebDriver server for a session in the chosen browser
+    pub fn capabilities(&self) -> WebDriverResult<Capabilities> {
+        match self.browser {
+            Browser::Chrome => {
+                let mut caps = DesiredCapabilities::chrome();
+                if self.headless {
+                    caps.set_headless()?;
+                    caps.add_chrome_arg(&format!("--window-size={},{}", HEADLESS_WINDOW_SIZE.0, HEADLESS_WINDOW_SIZE.1))?;
+                }
+                Ok(caps.into())
+            },
+            Browser::Firefox => {
+                let mut caps = DesiredCapabilities::firefox();
+                if self.headless {
+                    caps.set_headless()?;
+                    caps.add_firefox_arg(&format!("--width={}", HEADLESS_WINDOW_SIZE.0))?;
+                    caps.add_firefox_arg(&format!("--height={}", HEADLESS_WINDOW_SIZE.1))?;
+                }
+                Ok(caps.into())
+            },
+        }
+    }
+}
+
+/// How a game on the site ended, with the winner where there is one
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameEndReason {
+    Checkmate(PieceColor),
+    Resignation(PieceColor),
+    Timeout(PieceColor),
+    Abandonment(PieceColor),
+    Stalemate,
+    Agreement,
+    Repetition,
+    FiftyMoveRule,
+    /// Includes running out of time when the opponent couldn't have mated
+    InsufficientMaterial,
+    Aborted,
+}
+
+impl GameEndReason {
+    /// Reads the text of chess.com's game over modal, such as "You Won! by checkmate" or
+    /// "Black Won on time". A named player winning is taken to be the opponent, as the
+    /// modal says "You Won" when it's us
+    pub fn from_modal_text(text: &str, player_color: &PieceColor) -> Option<GameEndReason> {
+        let text = text.to_lowercase();
+
+        if text.contains("aborted") {
+            return Some(GameEndReason::Aborted);
+        }
+
+        // Draws first, as "timeout vs insufficient material" mentions time
+        if text.contains("insufficient material") {
+            return Some(GameEndReason::InsufficientMaterial);
+        }
+        else if text.contains("stalemate") {
+            return Some(GameEndReason::Stalemate);
+        }
+        else if text.contains("repetition") {
+            return Some(GameEndReason::Repetition);
+        }
+        else if text.contains("50-move") || text.contains("50 move") {
+            return Some(GameEndReason::FiftyMoveRule);
+        }
+        else if text.contains("agreement") {
+            return Some(GameEndReason::Agreement);
+        }
+
+        let winner = if text.contains("white won") {
+            PieceColor::White
+        }
+        else if text.contains("black won") {
+            PieceColor::Black
+        }
+        else if text.contains("you won") {
+            *player_color
+        }
+        else if text.contains("won") || text.contains("you lost") {
+            !*player_color
+        }
+        else {
+            return None;
+        };
+
+        if text.contains("checkmate") {
+            Some(GameEndReason::Checkmate(winner))
+        }
+        else if text.contains("resign") {
+            Some(GameEndReason::Resignation(winner))
+        }
+        else if text.contains("on time") || text.contains("timeout") {
+            Some(GameEndReason::Timeout(winner))
+        }
+        else if text.contains("abandon") {
+            Some(GameEndReason::Abandonment(winner))
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for GameEndReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |color: &PieceColor| match color {
+            PieceColor::White => "White",
+            PieceColor::Black => "Black",
+        };
+
+        match self {
+            GameEndReason::Checkmate(winner) => write!(f, "{} won by checkmate", name(winner)),
+            GameEndReason::Resignation(winner) => write!(f, "{} won by resignation", name(winner)),
+            GameEndReason::Timeout(winner) => write!(f, "{} won on time", name(winner)),
+            GameEndReason::Abandonment(winner) => write!(f, "{} won by abandonment", name(winner)),
+            GameEndReason::Stalemate => write!(f, "Draw by stalemate"),
+            GameEndReason::Agreement => write!(f, "Draw by agreement"),
+            GameEndReason::Repetition => write!(f, "Draw by repetition"),
+            GameEndReason::FiftyMoveRule => write!(f, "Draw by the 50 move rule"),
+            GameEndReason::InsufficientMaterial => write!(f, "Draw by insufficient material"),
+            GameEndReason::Aborted => write!(f, "Game aborted"),
+        }
+    }
+}
+
+/// Our answer to a predicted opponent reply, held until the opponent's move can be checked against it
+struct Premove {
+    reply: ChessMove,
+    game_after_reply: Game,
+    response: ChessMove,
+}
+
 pub struct Client {
     board_pieces: Vec<(Piece, Position)>,
     driver: WebDriver,
+    premove: Option<Premove>,
 }
 
 impl Client {
 
     pub async fn new(phpsessid: String) -> WebDriverResult<Client> {
-        let caps = DesiredCapabilities::chrome();
-        let driver = WebDriver::new("http://localhost:9515", caps).await.expect("Unable to connect to WebDriver");
+        Client::with_config(phpsessid, &ClientConfig::default()).await
+    }
+
+    pub async fn with_config(phpsessid: String, config: &ClientConfig) -> WebDriverResult<Client> {
+        let driver = WebDriver::new(&config.webdriver_url, config.capabilities()?).await?;
 
 
         // navigate to chess.com and set the session id cookie to use pre-existing authentication
@@ -30,23 +232,59 @@ impl Client {
         cookie.set_same_site(Some(SameSite::Lax));
         driver.add_cookie(cookie).await.unwrap();
         driver.refresh().await.unwrap();
-        Ok(Client{board_pieces: vec!(), driver})
+        Ok(Client{board_pieces: vec!(), driver, premove: None})
+    }
+
+    /// Ends the WebDriver session so the browser is closed
+    pub async fn disconnect(self) -> WebDriverResult<()> {
+        self.driver.quit().await
     }
 
     pub async fn get_player_color(&mut self) -> WebDriverResult<PieceColor> {
+        let player_color = self.read_player_color().await?;
+        match player_color {
+            PieceColor::Black => info!("Playing as Black!"),
+            PieceColor::White => info!("Playing as White!"),
+        }
+
+        Ok(player_color)
+    }
+
+    async fn read_player_color(&self) -> WebDriverResult<PieceColor> {
         // TODO: Handle unwrapping better
         let classes = self.driver.find(By::Css("chess-board.board")).await?.class_name().await?.expect("Could not locate board element!");
 
         if classes.contains("flipped") {
-            println!("Playing as Black!");
             Ok(PieceColor::Black)
         }
         else {
-            println!("Playing as White!");
             Ok(PieceColor::White)
         }
     }
 
+    /// Reads a side's remaining time from its clock above or below the board
+    pub async fn get_remaining_time(&self, color: &PieceColor) -> WebDriverResult<Duration> {
+        let color_name = match color {
+            PieceColor::White => "white",
+            PieceColor::Black => "black",
+        };
+
+        let text = self.driver.find(By::Css(format!(".clock-{} [data-cy=\"clock-time\"]", color_name).as_str())).await?.text().await?;
+        parse_clock(&text).ok_or_else(|| WebDriverError::CustomError(format!("Could not read clock time \"{}\"", text)))
+    }
+
+    /// Reads how the game ended from the game over modal, or None if it isn't showing or
+    /// its text isn't recognized
+    pub async fn get_game_result(&self) -> WebDriverResult<Option<GameEndReason>> {
+        let Ok(modal) = self.driver.find(By::Css(".game-over-modal-content")).await else {
+            return Ok(None);
+        };
+
+        let text = modal.text().await?;
+        debug!("Game over modal: {}", text);
+        Ok(GameEndReason::from_modal_text(&text, &self.read_player_color().await?))
+    }
+
     pub fn update_pieces_from_board(&mut self, board: &Board) {
         self.board_pieces = vec!();
         for row in 0usize..=7usize {
@@ -93,7 +331,7 @@ impl Client {
                     piece_positions.push((piece, position));
                 }
                 else {
-                    println!("Could not find position for piece");
+                    warn!("Could not find position for piece");
                 }
             }
         }
@@ -150,7 +388,7 @@ impl Client {
                     piece_positions.push((piece, position));
                 }
                 else {
-                    println!("Could not find position for piece");
+                    warn!("Could not find position for piece");
                 }
             }
         }
@@ -210,7 +448,7 @@ impl Client {
                     piece_positions.push((piece, position));
                 }
                 else {
-                    println!("Could not find position for piece");
+                    warn!("Could not find position for piece");
                 }
             }
         }
@@ -243,25 +481,25 @@ impl Client {
                     return Ok(Some(ChessMove::CastleQueenside));
                 }
             }
-            println!("Failed to recognize castle move");
-            println!("Previous State");
+            warn!("Failed to recognize castle move");
+            debug!("Previous State");
             for (piece, position) in self.board_pieces.iter() {
-                println!("{} {}", piece.to_char(), position);
+                debug!("{} {}", piece.to_char(), position);
             }
 
-            println!("Next State");
+            debug!("Next State");
             for (piece, position) in piece_positions.iter() {
-                println!("{} {}", piece.to_char(), position);
+                debug!("{} {}", piece.to_char(), position);
             }
 
-            println!("From Diff");
+            debug!("From Diff");
             for (piece, position) in from_piece_positions.iter() {
-                println!("{} {}", piece.to_char(), position);
+                debug!("{} {}", piece.to_char(), position);
             }
 
-            println!("To Diff");
+            debug!("To Diff");
             for (piece, position) in to_piece_positions.iter() {
-                println!("{} {}", piece.to_char(), position);
+                debug!("{} {}", piece.to_char(), position);
             }
         }
         else if let Some((Piece{piece_type: from_piece_type, color: _}, from)) = from_piece_positions.iter().find(|(Piece{piece_type:_, color}, _)| color == player_color) {
@@ -275,13 +513,14 @@ impl Client {
                     return Ok(Some(ChessMove::Move(*from, *to)))
                 }
             }
-            println!("Failed to recognize move");
+            warn!("Failed to recognize move");
         }
 
         Ok(None)
     }
 
-    pub async fn make_move(&mut self, chess_move: &ChessMove, player_color: &PieceColor) -> WebDriverResult<()> {
+    /// Clicks a move on the page and waits for the board to show `expected`, the game after it
+    pub async fn make_move(&mut self, chess_move: &ChessMove, player_color: &PieceColor, expected: &Game) -> WebDriverResult<()> {
         // <div class="promotion-window top" style="transform: translateX(700%);">
         // <i class="close-button icon-font-chess x"></i>
         // <div class="promotion-piece wb"></div>
@@ -336,6 +575,199 @@ impl Client {
             self.driver.find(By::Css(format!(".promotion-window .promotion-piece.{}{}", player_color.to_char(), piece_type.to_char()).as_str())).await?.click().await?;
         }
 
+        // Make sure the site actually accepted the move, however long it takes to animate
+        let deadline = Instant::now() + MOVE_LANDED_TIMEOUT;
+        while !self.has_move_landed(expected).await? {
+            if Instant::now() >= deadline {
+                return Err(WebDriverError::CustomError(format!("Move {} was not accepted by the board", chess_move)));
+            }
+            tokio::time::sleep(MOVE_LANDED_POLL).await;
+        }
+
         Ok(())
     }
+
+    /// Whether the page shows `expected`, the game after our move, possibly with an
+    /// opponent's reply already played on top of it
+    pub async fn has_move_landed(&self, expected: &Game) -> WebDriverResult<bool> {
+        Ok(Client::move_landed(expected, &self.get_new_board().await?))
+    }
+
+    /// Stages `response` to be played the moment the opponent answers with `reply`, which
+    /// leaves `game_after_reply`. Nothing is clicked until play_premove sees that board, so
+    /// if the opponent plays something else the staged move is simply dropped
+    pub fn make_premove(&mut self, reply: ChessMove, game_after_reply: Game, response: ChessMove) {
+        self.premove = Some(Premove{reply, game_after_reply, response});
+    }
+
+    /// Plays the staged premove if the board now shows its predicted reply, returning the
+    /// reply and our response. Returns None, with the premove dropped, for any other board
+    pub async fn play_premove(&mut self, player_color: &PieceColor) -> WebDriverResult<Option<(ChessMove, ChessMove)>> {
+        let Some(premove) = self.premove.take() else {
+            return Ok(None);
+        };
+
+        let board = self.get_new_board().await?;
+        if board != premove.game_after_reply.board {
+            debug!("Opponent didn't play the predicted {}", premove.reply);
+            return Ok(None);
+        }
+
+        // The reply is only accepted once our response has been, so a failure leaves the
+        // opponent's move to be found the usual way
+        let board_pieces = self.board_pieces.clone();
+        self.update_pieces_from_board(&board);
+        let expected = premove.game_after_reply.peek_move(&premove.response);
+        if let Err(error) = self.make_move(&premove.response, player_color, &expected).await {
+            self.board_pieces = board_pieces;
+            return Err(error);
+        }
+
+        Ok(Some((premove.reply, premove.response)))
+    }
+
+    /// Whether the board read back from the page is `expected`, the game after our move, or
+    /// that game after any reply, since an opponent's premove can answer before we look
+    fn move_landed(expected: &Game, after: &Board) -> bool {
+        expected.board == *after || expected.get_moves().iter().any(|reply| expected.peek_move(reply).board == *after)
+    }
+}
+
+/// Reads a clock time such as "1:05:00", "1:05.3" or "0:09"
+fn parse_clock(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let (whole, fraction) = match text.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (text, None),
+    };
+    let is_number = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+
+    // Hours, minutes and seconds, with the larger units left off when they're zero
+    let parts: Vec<&str> = whole.split(':').collect();
+    if parts.len() > 3 || !parts.iter().all(|part| is_number(part)) {
+        return None;
+    }
+
+    let seconds = parts.iter().try_fold(0u64, |seconds, part| Some(seconds * 60 + part.parse::<u64>().ok()?))?;
+    // Tenths of a second, shown once the clock runs low
+    let millis = match fraction {
+        None => 0,
+        Some(fraction) if is_number(fraction) => format!("{:0<3}", fraction)[..3].parse::<u64>().ok()?,
+        Some(_) => return None,
+    };
+
+    Some(Duration::from_secs(seconds) + Duration::from_millis(millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::START_FEN;
+
+    #[test]
+    fn test_game_end_reason_from_modal_text() {
+        let white = PieceColor::White;
+        let reason = |text: &str| GameEndReason::from_modal_text(text, &white);
+
+        assert!(reason("You Won!\nby checkmate") == Some(GameEndReason::Checkmate(PieceColor::White)));
+        assert!(reason("opponent123 Won\nby checkmate") == Some(GameEndReason::Checkmate(PieceColor::Black)));
+        assert!(reason("Black Won\nby resignation") == Some(GameEndReason::Resignation(PieceColor::Black)));
+        assert!(reason("You Won!\nopponent123 resigned") == Some(GameEndReason::Resignation(PieceColor::White)));
+        assert!(reason("White Won\non time") == Some(GameEndReason::Timeout(PieceColor::White)));
+        assert!(reason("opponent123 Won\nby abandonment") == Some(GameEndReason::Abandonment(PieceColor::Black)));
+        assert!(reason("Draw\nby stalemate") == Some(GameEndReason::Stalemate));
+        assert!(reason("Draw\nby agreement") == Some(GameEndReason::Agreement));
+        assert!(reason("Draw\nby repetition") == Some(GameEndReason::Repetition));
+        assert!(reason("Draw\nby 50-move rule") == Some(GameEndReason::FiftyMoveRule));
+        assert!(reason("Draw\nby insufficient material") == Some(GameEndReason::InsufficientMaterial));
+        assert!(reason("Draw\ntimeout vs insufficient material") == Some(GameEndReason::InsufficientMaterial));
+        assert!(reason("Game Aborted") == Some(GameEndReason::Aborted));
+        assert!(reason("Rematch\nNew 10 min").is_none());
+
+        // Playing black, a named winner is still the opponent
+        assert!(GameEndReason::from_modal_text("opponent123 Won\non time", &PieceColor::Black) == Some(GameEndReason::Timeout(PieceColor::White)));
+        assert!(GameEndReason::Timeout(PieceColor::White).to_string() == "White won on time");
+    }
+
+    #[test]
+    fn test_parse_clock() {
+        assert!(parse_clock("1:05.3") == Some(Duration::from_millis(65_300)));
+        assert!(parse_clock("0:09") == Some(Duration::from_secs(9)));
+        assert!(parse_clock(" 10:00 ") == Some(Duration::from_secs(600)));
+        assert!(parse_clock("1:00:00") == Some(Duration::from_secs(3600)));
+        assert!(parse_clock("0:00.05") == Some(Duration::from_millis(50)));
+        assert!(parse_clock("").is_none());
+        assert!(parse_clock("1:xx").is_none());
+        assert!(parse_clock("1::05").is_none());
+        assert!(parse_clock("1:05.").is_none());
+    }
+
+    #[test]
+    fn test_client_config() {
+        let config = ClientConfig::default();
+        assert!(config.webdriver_url == "http://localhost:9515" && config.browser == Browser::Chrome && !config.headless);
+        assert!(config.capabilities().expect("").get("browserName").is_some_and(|name| name == "chrome"));
+
+        let config = ClientConfig{webdriver_url: "http://grid:4444".to_owned(), browser: Browser::Firefox, headless: false};
+        assert!(config.capabilities().expect("").get("browserName").is_some_and(|name| name == "firefox"));
+    }
+
+    #[test]
+    fn test_client_config_headless() {
+        let browser_args = |config: &ClientConfig, options: &str| {
+            let caps = config.capabilities().expect("");
+            caps.get(options).and_then(|options| options["args"].as_array().cloned()).unwrap_or_default()
+        };
+
+        let mut config = ClientConfig::default();
+        assert!(!browser_args(&config, "goog:chromeOptions").contains(&"--headless".into()));
+
+        config.headless = true;
+        let args = browser_args(&config, "goog:chromeOptions");
+        assert!(args.contains(&"--headless".into()) && args.contains(&"--window-size=1920,1080".into()));
+
+        config.browser = Browser::Firefox;
+        let args = browser_args(&config, "moz:firefoxOptions");
+        assert!(args.contains(&"--headless".into()) && args.contains(&"--width=1920".into()));
+    }
+
+    #[test]
+    fn test_shutdown_is_shared_between_clones() {
+        let shutdown = Shutdown::new();
+        let handler = shutdown.clone();
+        assert!(!shutdown.is_requested());
+
+        std::thread::spawn(move || handler.request()).join().expect("");
+        assert!(shutdown.is_requested());
+    }
+
+    fn game_after(fen: &str, move_str: &str) -> Game {
+        let game = Game::from_fen(fen).expect("");
+        game.peek_move(&game.parse_move(move_str).expect(""))
+    }
+
+    #[test]
+    fn test_move_landed() {
+        let expected = game_after(START_FEN, "e2e4");
+        assert!(Client::move_landed(&expected, &expected.board));
+        assert!(!Client::move_landed(&expected, &game_after(START_FEN, "e2e3").board));
+
+        // A rejected move leaves the board unchanged
+        assert!(!Client::move_landed(&expected, &Game::new().board));
+
+        // An opponent's premove may already have answered, even taking the piece that moved
+        let expected = game_after("rnbqkbnr/ppp1pppp/8/3p4/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 2", "e2e4");
+        assert!(Client::move_landed(&expected, &expected.peek_move(&ChessMove::from_str("d5e4").expect("")).board));
+        let two_plies_on = expected.peek_move(&ChessMove::from_str("d5e4").expect("")).peek_move(&ChessMove::from_str("a2a3").expect(""));
+        assert!(!Client::move_landed(&expected, &two_plies_on.board));
+    }
+
+    #[test]
+    fn test_move_landed_promotion() {
+        let fen = "7k/P7/8/8/8/8/8/K7 w - - 0 1";
+        let expected = game_after(fen, "a7a8q");
+
+        assert!(Client::move_landed(&expected, &expected.board));
+        assert!(!Client::move_landed(&expected, &game_after(fen, "a7a8n").board));
+    }
 }
@@ -0,0 +1,378 @@
+use super::GameEndReason;
+use crate::game::chess_move::ChessMove;
+use crate::game::piece::PieceColor;
+use crate::game::Game;
+use eyre::{eyre, Result, WrapErr};
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper::header::AUTHORIZATION;
+use hyper::{Body, Method, Request};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use log::{debug, info};
+use serde_json::Value;
+use std::time::Duration;
+
+const LICHESS_URL: &str = "https://lichess.org";
+
+/// Plays on Lichess through the Board API, as an alternative to the chess.com browser client.
+///
+/// The token is a Lichess personal API token with the `board:play` scope. Moves come from
+/// the game's event stream instead of the page, so nothing needs to watch a browser
+pub struct LichessClient {
+    http: hyper::Client<HttpsConnector<HttpConnector>>,
+    token: String,
+    game_id: String,
+    player_color: PieceColor,
+    events: LineStream,
+    state: GameState,
+}
+
+impl LichessClient {
+    /// Connects with a personal API token and waits for a game to start, such as a
+    /// challenge being accepted or a seek being paired
+    pub async fn new(token: String) -> Result<LichessClient> {
+        let connector = HttpsConnectorBuilder::new().with_native_roots().https_only().enable_http1().build();
+        let http = hyper::Client::builder().build(connector);
+
+        let mut account_events = LineStream::new(LichessClient::get(&http, &token, "/api/stream/event").await?);
+        let (game_id, player_color) = loop {
+            let line = account_events.next_line().await?.ok_or_else(|| eyre!("Lichess closed the event stream before a game started"))?;
+            if let Some(game) = parse_game_start(&line)? {
+                break game;
+            }
+        };
+        info!("Lichess game {} started", game_id);
+
+        let mut client = LichessClient {
+            events: LineStream::new(LichessClient::get(&http, &token, &format!("/api/board/game/stream/{}", game_id)).await?),
+            http,
+            token,
+            game_id,
+            player_color,
+            state: GameState::default(),
+        };
+
+        // The first event holds the starting position and any moves already played
+        while !client.state.has_game {
+            let line = client.events.next_line().await?.ok_or_else(|| eyre!("Lichess closed the game stream before sending the game"))?;
+            client.state.apply_event(&line)?;
+        }
+
+        Ok(client)
+    }
+
+    pub async fn get_player_color(&mut self) -> Result<PieceColor> {
+        Ok(self.player_color)
+    }
+
+    /// The game as Lichess last reported it
+    pub fn game(&self) -> &Game {
+        &self.state.game
+    }
+
+    /// Whether Lichess has reported the game as finished, by mate, resignation, timeout or otherwise
+    pub fn is_game_over(&self) -> bool {
+        self.state.is_over()
+    }
+
+    /// A side's time left as of Lichess's last update, which comes with every move
+    pub async fn get_remaining_time(&self, color: &PieceColor) -> Result<Duration> {
+        self.state.clocks[*color as usize].ok_or_else(|| eyre!("Lichess game {} has no clock", self.game_id))
+    }
+
+    /// How the game ended, once Lichess reports it as over
+    pub fn get_game_result(&self) -> Option<GameEndReason> {
+        self.state.end_reason()
+    }
+
+    /// Waits for the next move by `player_color`, the opponent's color as with Client, or
+    /// None once the game is over
+    pub async fn get_opponent_move(&mut self, player_color: &PieceColor) -> Result<Option<ChessMove>> {
+        loop {
+            if self.state.is_over() {
+                return Ok(None);
+            }
+
+            let Some(line) = self.events.next_line().await? else {
+                self.state.status = "closed".to_owned();
+                return Ok(None);
+            };
+
+            // Our own moves are echoed back on the same stream
+            let new_moves = self.state.apply_event(&line)?;
+            if let Some((_, chess_move)) = new_moves.into_iter().rev().find(|(mover, _)| mover == player_color) {
+                return Ok(Some(chess_move));
+            }
+        }
+    }
+
+    pub async fn make_move(&mut self, chess_move: &ChessMove, player_color: &PieceColor) -> Result<()> {
+        let path = format!("/api/board/game/{}/move/{}", self.game_id, chess_move.to_uci(player_color));
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}{}", LICHESS_URL, path))
+            .header(AUTHORIZATION, format!("Bearer {}", self.token))
+            .body(Body::empty())?;
+
+        let response = self.http.request(request).await.wrap_err_with(|| format!("Could not reach Lichess for {}", path))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap_or_default();
+            return Err(eyre!("Move {} was not accepted by Lichess ({}): {}", chess_move, status, String::from_utf8_lossy(&body)));
+        }
+
+        Ok(())
+    }
+
+    /// Opens a streamed GET request, failing on any status other than success
+    async fn get(http: &hyper::Client<HttpsConnector<HttpConnector>>, token: &str, path: &str) -> Result<Body> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(format!("{}{}", LICHESS_URL, path))
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())?;
+
+        let response = http.request(request).await.wrap_err_with(|| format!("Could not reach Lichess for {}", path))?;
+        if !response.status().is_success() {
+            return Err(eyre!("Lichess answered {} for {}", response.status(), path));
+        }
+
+        Ok(response.into_body())
+    }
+}
+
+/// Splits a streamed body into the newline separated JSON objects Lichess sends
+struct LineStream {
+    body: Body,
+    buffer: Vec<u8>,
+}
+
+impl LineStream {
+    fn new(body: Body) -> LineStream {
+        LineStream {
+            body,
+            buffer: vec!(),
+        }
+    }
+
+    /// Gets the next non-empty line, skipping the empty lines Lichess sends to keep the
+    /// connection alive, or None once the stream ends
+    async fn next_line(&mut self) -> Result<Option<String>> {
+        loop {
+            while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line).trim().to_owned();
+                if !line.is_empty() {
+                    return Ok(Some(line));
+                }
+            }
+
+            match self.body.data().await {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk.wrap_err("Lichess stream failed")?),
+                None => {
+                    let line = String::from_utf8_lossy(&self.buffer).trim().to_owned();
+                    self.buffer.clear();
+                    return Ok(if line.is_empty() { None } else { Some(line) });
+                },
+            }
+        }
+    }
+}
+
+/// Reads a gameStart event from the account event stream into the game's id and our color
+fn parse_game_start(line: &str) -> Result<Option<(String, PieceColor)>> {
+    let event: Value = serde_json::from_str(line).wrap_err_with(|| format!("Could not parse Lichess event {}", line))?;
+    if event["type"] != "gameStart" {
+        return Ok(None);
+    }
+
+    let game = &event["game"];
+    let game_id = game["gameId"].as_str().or_else(|| game["id"].as_str()).ok_or_else(|| eyre!("Lichess game start has no game id"))?;
+    let player_color = match game["color"].as_str() {
+        Some("white") => PieceColor::White,
+        Some("black") => PieceColor::Black,
+        _ => return Err(eyre!("Lichess game start has no color")),
+    };
+
+    Ok(Some((game_id.to_owned(), player_color)))
+}
+
+/// The game as rebuilt from the game stream's move lists
+struct GameState {
+    game: Game,
+    // Whether the gameFull event with the starting position has arrived
+    has_game: bool,
+    // Moves from the move list already applied to game
+    moves_played: usize,
+    status: String,
+    winner: Option<PieceColor>,
+    // Each side's time left as of the last event, by PieceColor, for timed games
+    clocks: [Option<Duration>; 2],
+}
+
+impl GameState {
+    fn default() -> GameState {
+        GameState {
+            game: Game::new(),
+            has_game: false,
+            moves_played: 0,
+            status: "created".to_owned(),
+            winner: None,
+            clocks: [None; 2],
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.status != "created" && self.status != "started"
+    }
+
+    /// Reads the Lichess game status, as documented for the Board API, into how the game ended
+    fn end_reason(&self) -> Option<GameEndReason> {
+        match (self.status.as_str(), self.winner) {
+            ("mate", Some(winner)) => Some(GameEndReason::Checkmate(winner)),
+            ("resign", Some(winner)) => Some(GameEndReason::Resignation(winner)),
+            ("outoftime", Some(winner)) => Some(GameEndReason::Timeout(winner)),
+            // Out of time with no winner means the other side couldn't have mated
+            ("outoftime", None) => Some(GameEndReason::InsufficientMaterial),
+            // The opponent left and the winner claimed the game
+            ("timeout", Some(winner)) => Some(GameEndReason::Abandonment(winner)),
+            ("stalemate", _) => Some(GameEndReason::Stalemate),
+            ("draw", _) => Some(GameEndReason::Agreement),
+            ("aborted" | "noStart", _) => Some(GameEndReason::Aborted),
+            _ => None,
+        }
+    }
+
+    /// Applies a game stream event, returning the moves it added along with who played them
+    fn apply_event(&mut self, line: &str) -> Result<Vec<(PieceColor, ChessMove)>> {
+        let event: Value = serde_json::from_str(line).wrap_err_with(|| format!("Could not parse Lichess event {}", line))?;
+
+        let state = match event["type"].as_str() {
+            Some("gameFull") => {
+                self.game = match event["initialFen"].as_str() {
+                    None | Some("startpos") => Game::new(),
+                    Some(fen) => Game::from_fen(fen).wrap_err("Lichess sent an invalid starting position")?,
+                };
+                self.has_game = true;
+                self.moves_played = 0;
+                &event["state"]
+            },
+            Some("gameState") => &event,
+            _ => {
+                debug!("Ignoring Lichess event {}", line);
+                return Ok(vec!());
+            },
+        };
+
+        if let Some(status) = state["status"].as_str() {
+            self.status = status.to_owned();
+        }
+
+        for (color, field) in [(PieceColor::White, "wtime"), (PieceColor::Black, "btime")] {
+            if let Some(millis) = state[field].as_u64() {
+                self.clocks[color as usize] = Some(Duration::from_millis(millis));
+            }
+        }
+
+        self.winner = match state["winner"].as_str() {
+            Some("white") => Some(PieceColor::White),
+            Some("black") => Some(PieceColor::Black),
+            _ => None,
+        };
+
+        let moves: Vec<&str> = state["moves"].as_str().unwrap_or("").split_whitespace().collect();
+        if moves.len() < self.moves_played {
+            return Err(eyre!("Lichess took back moves, which the bot can't follow"));
+        }
+
+        let mut new_moves = vec!();
+        for move_str in &moves[self.moves_played..] {
+            let mover = self.game.turn;
+            let played = self.game.play_line(move_str).wrap_err("Lichess sent a move the game doesn't allow")?;
+            new_moves.extend(played.into_iter().map(|chess_move| (mover, chess_move)));
+        }
+        self.moves_played = moves.len();
+
+        Ok(new_moves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME_STREAM: &str = r#"{"type":"gameFull","id":"5IrD6Gzz","initialFen":"startpos","white":{"id":"chessbot"},"black":{"id":"opponent"},"state":{"type":"gameState","moves":"","status":"started"}}
+
+{"type":"gameState","moves":"e2e4","status":"started"}
+{"type":"chatLine","username":"opponent","text":"hf","room":"player"}
+{"type":"gameState","moves":"e2e4 e7e5","status":"started"}
+{"type":"gameState","moves":"e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1g1","status":"started"}
+{"type":"gameState","moves":"e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1g1 f8c5","status":"resign","winner":"white"}
+"#;
+
+    #[test]
+    fn test_game_stream() {
+        let mut state = GameState::default();
+        let mut lines = LineStream::new(Body::from(GAME_STREAM));
+        let mut next_moves = || {
+            let line = futures::executor::block_on(lines.next_line()).expect("").expect("");
+            state.apply_event(&line).expect("").into_iter().map(|(mover, chess_move)| format!("{} {}", mover, chess_move)).collect::<Vec<String>>()
+        };
+
+        assert!(next_moves().is_empty());
+        assert!(next_moves() == vec!["w e2e4"]);
+        assert!(next_moves().is_empty());
+        assert!(next_moves() == vec!["b e7e5"]);
+
+        // Several moves at once, with castling in the king's coordinates
+        assert!(next_moves() == vec!["w g1f3", "b b8c6", "w f1c4", "b g8f6", "w O-O"]);
+        assert!(next_moves() == vec!["b f8c5"]);
+        assert!(state.end_reason() == Some(GameEndReason::Resignation(PieceColor::White)));
+    }
+
+    #[test]
+    fn test_game_over() {
+        const GAME_FULL: &str = r#"{"type":"gameFull","initialFen":"7k/8/5K2/8/8/8/8/6R1 w - - 0 1","state":{"moves":"f6f7","wtime":58200,"btime":60000,"status":"started"}}"#;
+
+        let mut state = GameState::default();
+        assert!(state.apply_event(GAME_FULL).expect("") == vec![(PieceColor::White, ChessMove::from_str("f6f7").expect(""))]);
+        assert!(state.has_game && !state.is_over());
+        assert!(state.clocks == [Some(Duration::from_secs(60)), Some(Duration::from_millis(58_200))]);
+
+        let moves = state.apply_event(r#"{"type":"gameState","moves":"f6f7 h8h7 g1h1","status":"mate","winner":"white"}"#).expect("");
+        assert!(moves.len() == 2 && state.is_over());
+        assert!(state.end_reason() == Some(GameEndReason::Checkmate(PieceColor::White)));
+        assert!(state.game.to_fen().starts_with("8/5K1k/8/8/8/8/8/7R b"));
+
+        // Moves that can't be played and moves taken back are errors
+        let mut state = GameState::default();
+        state.apply_event(GAME_FULL).expect("");
+        let moves = state.apply_event(r#"{"type":"gameState","moves":"f6f7 h8h6","status":"started"}"#);
+        assert!(moves.is_err_and(|error| format!("{:#}", error).contains("doesn't allow")));
+
+        let mut state = GameState::default();
+        state.apply_event(GAME_FULL).expect("");
+        let moves = state.apply_event(r#"{"type":"gameState","moves":"","status":"started"}"#);
+        assert!(moves.is_err_and(|error| error.to_string().contains("took back")));
+    }
+
+    #[test]
+    fn test_parse_game_start() {
+        let game_start = r#"{"type":"gameStart","game":{"gameId":"5IrD6Gzz","fullId":"5IrD6Gzzabcd","color":"black","fen":"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"}}"#;
+        assert!(parse_game_start(game_start).expect("") == Some(("5IrD6Gzz".to_owned(), PieceColor::Black)));
+        assert!(parse_game_start(r#"{"type":"challenge","challenge":{"id":"x"}}"#).expect("").is_none());
+        assert!(parse_game_start(r#"{"type":"gameStart","game":{"gameId":"5IrD6Gzz"}}"#).is_err());
+        assert!(parse_game_start("not json").is_err());
+    }
+
+    #[test]
+    fn test_line_stream_split_chunks() {
+        // Lines may be split across chunks and the last may have no newline
+        let chunks: Vec<Result<&str, std::io::Error>> = vec!(Ok("{\"a\":"), Ok("1}\n\n{\"b\""), Ok(":2}"));
+        let mut lines = LineStream::new(Body::wrap_stream(futures::stream::iter(chunks)));
+
+        assert!(futures::executor::block_on(lines.next_line()).expect("") == Some("{\"a\":1}".to_owned()));
+        assert!(futures::executor::block_on(lines.next_line()).expect("") == Some("{\"b\":2}".to_owned()));
+        assert!(futures::executor::block_on(lines.next_line()).expect("").is_none());
+    }
+}
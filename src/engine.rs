@@ -1,15 +1,261 @@
-use super::game::{chess_move::ChessMove, piece::*, position::Position, Game};
-use lazy_static::lazy_static;
-use rand::Rng;
+pub mod book;
+
+use book::OpeningBook;
+use super::game::{board::{Board, FileStatus}, chess_move::ChessMove, piece::*, position::Position, Game};
+use log::debug;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::cmp;
 use rayon::prelude::*;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Largest magnitude evaluate_state returns, leaving room inside i32 for the
+/// checkmate scores the search builds from i32::MIN and i32::MAX
+pub const EVAL_BOUND: i32 = 1_000_000;
+
+// Most captures quiescence follows past the end of the main search
+const QUIESCENCE_DEPTH: u16 = 4;
+
+// How much shallower than a normal reply the search after a null move goes
+const NULL_MOVE_REDUCTION: u16 = 2;
+
+// Pawn values by relative rank then file
+const PAWN_TABLE: [[i32; 8]; 8] = [
+    [100, 100, 100, 100, 100, 100, 100, 100],
+    [100, 100, 100, 100, 100, 100, 100, 100],
+    [100, 100, 115, 115, 115, 115, 100, 100],
+    [105, 105, 120, 120, 120, 120, 105, 105],
+    [120, 120, 120, 120, 120, 120, 120, 120],
+    [120, 120, 120, 120, 120, 120, 120, 120],
+    [120, 120, 120, 120, 120, 120, 120, 120],
+    [100, 100, 100, 100, 100, 100, 100, 100],
+];
+
+// Knight values by square before EvalParams::knight is added
+const KNIGHT_TABLE: [[i32; 8]; 8] = [
+    [250, 265, 275, 280, 280, 275, 265, 250],
+    [265, 285, 300, 305, 305, 300, 285, 265],
+    [275, 300, 315, 320, 320, 315, 300, 275],
+    [280, 305, 320, 330, 330, 320, 305, 280],
+    [280, 305, 320, 330, 330, 320, 305, 280],
+    [275, 300, 315, 320, 320, 315, 300, 275],
+    [265, 285, 300, 305, 305, 300, 285, 265],
+    [250, 265, 275, 280, 280, 275, 265, 250],
+];
+
+// King values by square for the first 30 half moves
+const KING_TABLE: [[i32; 8]; 8] = [
+    [250, 200, 50, 50, 100, 50, 200, 250],
+    [150, 150, 100, 50, 50, 50, 150, 150],
+    [100, 100, 100, 100, 100, 100, 100, 100],
+    [100, 100, 100, 100, 100, 100, 100, 100],
+    [100, 100, 100, 100, 100, 100, 100, 100],
+    [100, 100, 100, 100, 100, 100, 100, 100],
+    [150, 150, 100, 50, 50, 50, 150, 150],
+    [250, 200, 50, 50, 100, 50, 200, 250],
+];
+
+// King values by square from then on, subtracted so the king heads for the center
+const ENDGAME_KING_TABLE: [[i32; 8]; 8] = [
+    [150, 100, 75, 50, 50, 75, 100, 150],
+    [100, 100, 50, 40, 40, 50, 100, 100],
+    [75, 50, 50, 25, 25, 50, 50, 75],
+    [50, 40, 25, 0, 0, 25, 40, 50],
+    [50, 40, 25, 0, 0, 25, 40, 50],
+    [75, 50, 50, 25, 25, 50, 50, 75],
+    [100, 100, 50, 40, 40, 50, 100, 100],
+    [150, 100, 75, 50, 50, 75, 100, 150],
+];
+
+/// Gets how many half moves away a search score's forced mate is, positive when the
+/// engine's player gives the mate and negative when it is mated, or None for scores
+/// that aren't mates
+pub fn mate_distance(score: i32) -> Option<i32> {
+    if score > EVAL_BOUND {
+        Some(i32::MAX - score)
+    }
+    else if score < -EVAL_BOUND {
+        Some(-(score - i32::MIN))
+    }
+    else {
+        None
+    }
+}
+
+/// Material and positional weights used by evaluate_state
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EvalParams {
+    pub pawn_table: [[i32; 8]; 8],
+    pub knight_table: [[i32; 8]; 8],
+    pub king_table: [[i32; 8]; 8],
+    pub endgame_king_table: [[i32; 8]; 8],
+    pub queen: i32,
+    // Queen value while it has left its home square in the first ten half moves
+    pub early_queen: i32,
+    pub rook: i32,
+    pub bishup: i32,
+    pub bishup_pair_bonus: i32,
+    // Added to the knight table value
+    pub knight: i32,
+    pub knight_pair_bonus: i32,
+    // Pawn value gained per half move
+    pub pawn_growth: i32,
+    pub castle_right_bonus: i32,
+    pub opponent_castle_right_penalty: i32,
+    pub castled_bonus: i32,
+    pub rook_open_file_bonus: i32,
+    pub rook_half_open_file_bonus: i32,
+    pub doubled_rooks_bonus: i32,
+    pub connected_rooks_bonus: i32,
+    pub rook_behind_passed_pawn_bonus: i32,
+    pub knight_outpost_bonus: i32,
+    // Per piece attacking a square next to the enemy king
+    pub king_attack_bonus: i32,
+    // Per rank a pawn near the enemy king's file has advanced
+    pub pawn_storm_bonus: i32,
+}
+
+impl EvalParams {
+    /// The weights the engine plays with by default
+    pub fn standard() -> EvalParams {
+        EvalParams {
+            pawn_table: PAWN_TABLE,
+            knight_table: KNIGHT_TABLE,
+            king_table: KING_TABLE,
+            endgame_king_table: ENDGAME_KING_TABLE,
+            queen: 1000,
+            early_queen: 800,
+            rook: 500,
+            bishup: 425,
+            bishup_pair_bonus: 100,
+            knight: 100,
+            knight_pair_bonus: 100,
+            pawn_growth: 2,
+            castle_right_bonus: 50,
+            opponent_castle_right_penalty: 25,
+            castled_bonus: 200,
+            rook_open_file_bonus: 25,
+            rook_half_open_file_bonus: 10,
+            doubled_rooks_bonus: 30,
+            connected_rooks_bonus: 20,
+            rook_behind_passed_pawn_bonus: 40,
+            knight_outpost_bonus: 40,
+            king_attack_bonus: 0,
+            pawn_storm_bonus: 0,
+        }
+    }
+
+    /// Overvalues minor pieces and activity and cares less about king safety,
+    /// so the engine will give up heavy material for knights and open files
+    pub fn aggressive() -> EvalParams {
+        EvalParams {
+            queen: 900,
+            early_queen: 850,
+            rook: 450,
+            knight: 250,
+            castle_right_bonus: 20,
+            opponent_castle_right_penalty: 10,
+            castled_bonus: 100,
+            rook_open_file_bonus: 50,
+            rook_half_open_file_bonus: 25,
+            knight_outpost_bonus: 80,
+            ..EvalParams::standard()
+        }
+    }
+
+    /// Plays for mate at any cost, gladly giving up pieces to pile attackers
+    /// around the enemy king and throwing pawns at its shelter
+    pub fn king_hunt() -> EvalParams {
+        EvalParams {
+            king_attack_bonus: 150,
+            pawn_storm_bonus: 20,
+            ..EvalParams::aggressive()
+        }
+    }
+}
+
+/// What the bot should do about the game given how its recent searches scored
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameDecision {
+    Continue,
+    Resign,
+    AcceptDraw,
+}
+
+/// Score limits, from the engine player's point of view, which the bot's searches have to
+/// stay below for several moves in a row before it gives up or settles for a draw
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DecisionThresholds {
+    pub resign: Option<i32>,
+    pub draw: Option<i32>,
+    // How many consecutive scores have to be past a threshold
+    pub moves: usize,
+}
+
+impl DecisionThresholds {
+    /// Decides based on the scores of the bot's moves so far, oldest first
+    pub fn decide(&self, scores: &[i32]) -> GameDecision {
+        let moves = cmp::max(self.moves, 1);
+        let is_sustained_below = |threshold: i32| scores.len() >= moves && scores[scores.len() - moves..].iter().all(|score| *score < threshold);
+
+        if self.resign.is_some_and(is_sustained_below) {
+            GameDecision::Resign
+        }
+        else if self.draw.is_some_and(is_sustained_below) {
+            GameDecision::AcceptDraw
+        }
+        else {
+            GameDecision::Continue
+        }
+    }
+}
+
+/// How a stored search value relates to the position's minimax value, since a search
+/// cut off by alpha-beta only proves a bound
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
 
 pub struct Engine {
     pub game: Game,
+    // Legal moves from the current position, refreshed whenever a move is advanced
+    legal_moves: Vec<ChessMove>,
     tree: Option<Node>,
     search_depth: u16,
     pub player: PieceColor,
+    pub eval_params: EvalParams,
+    // Consulted before searching in get_best_move_parallel
+    pub opening_book: Option<OpeningBook>,
+    // Set from another thread to make a running search return early
+    stop: Arc<AtomicBool>,
+    // Largest random amount added to or taken from each evaluation, 0 for a deterministic search
+    randomness: i32,
+    rng: Mutex<StdRng>,
+    pub use_transposition_table: bool,
+    // Whether leaves of the main search follow captures until the position is quiet
+    pub use_quiescence: bool,
+    // Whether search_tree tries captures and promotions first, by mvv_lva, rather than in generation order
+    pub use_move_ordering: bool,
+    // Whether search_tree prunes nodes where passing still fails high, off for exact results
+    pub use_null_move: bool,
+    // Whether quiet moves are ordered by killer moves and the history table
+    pub use_history_heuristics: bool,
+    // Two quiet moves per ply from the root which recently caused a beta cutoff
+    killer_moves: Mutex<Vec<[Option<ChessMove>; 2]>>,
+    // How much quiet moves between each pair of squares have caused cutoffs, by from then to square
+    history: Mutex<Box<[[i32; 64]; 64]>>,
+    // Positions visited by the last search, quiescence included
+    nodes: AtomicU64,
+    // Value, remaining depth and bound of each position searched, by Zobrist key.
+    // Cleared at the start of every search since evaluation depends on the move number
+    transposition_table: Mutex<HashMap<u64, (i32, u16, Bound)>>,
 }
 
 pub struct Node {
@@ -21,10 +267,33 @@ pub struct Node {
 impl Engine {
     pub fn new(game: Game, player: PieceColor, search_depth: u16) -> Engine {
         Engine {
+            legal_moves: game.get_moves(),
             game,
             tree: None,
             search_depth,
             player,
+            eval_params: EvalParams::standard(),
+            opening_book: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            randomness: 0,
+            rng: Mutex::new(StdRng::seed_from_u64(0)),
+            use_transposition_table: true,
+            use_quiescence: true,
+            use_move_ordering: true,
+            use_null_move: true,
+            use_history_heuristics: true,
+            killer_moves: Mutex::new(vec!()),
+            history: Mutex::new(Box::new([[0; 64]; 64])),
+            nodes: AtomicU64::new(0),
+            transposition_table: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Same as new but evaluating with `eval_params` instead of the standard weights
+    pub fn with_eval_params(game: Game, player: PieceColor, search_depth: u16, eval_params: EvalParams) -> Engine {
+        Engine {
+            eval_params,
+            ..Engine::new(game, player, search_depth)
         }
     }
 
@@ -36,12 +305,13 @@ impl Engine {
         mut alpha: i32,
         mut beta: i32,
     ) {
-        if root.depth >= depth {
+        // A node only needs searching again for a deeper iteration. Leaves are
+        // always depth 0 so they are evaluated whenever they are reached
+        if root.depth >= depth && depth > 0 {
             return;
         }
         root.depth = depth;
 
-        //
         if depth == 0 {
             root.value = self.evaluate_state(game);
             return;
@@ -51,7 +321,7 @@ impl Engine {
         if root
             .children
             .as_ref()
-            .map_or(false, |children| children.is_empty())
+            .is_some_and(|children| children.is_empty())
         {
             return;
         }
@@ -79,7 +349,7 @@ impl Engine {
                         Node {
                             children: None,
                             value: 0,
-                            depth: depth - 1,
+                            depth: 0,
                         },
                     ))
                 }
@@ -93,10 +363,9 @@ impl Engine {
 
         if game.turn == self.player {
             root.value = i32::MIN;
-            children.sort_unstable_by_key(|(_, a)| -a.value);
+            children.sort_unstable_by_key(|(_, a)| a.value.saturating_neg());
             for (chess_move, child) in children.iter_mut() {
-                let mut next_game = game.clone();
-                next_game.make_move(chess_move);
+                let next_game = game.peek_move(chess_move);
                 self.iterative_search(&next_game, child, depth - 1, alpha, beta);
                 root.value = cmp::max(root.value, child.value);
 
@@ -110,8 +379,7 @@ impl Engine {
             root.value = i32::MAX;
             children.sort_unstable_by_key(|(_, a)| a.value);
             for (chess_move, child) in children.iter_mut() {
-                let mut next_game = game.clone();
-                next_game.make_move(chess_move);
+                let next_game = game.peek_move(chess_move);
                 self.iterative_search(&next_game, child, depth - 1, alpha, beta);
 
                 root.value = cmp::min(root.value, child.value);
@@ -126,20 +394,64 @@ impl Engine {
     }
 
     // TODO: Implement iterative deepening
-    pub fn search_tree(&self, game: &Game, depth: u16, mut alpha: i32, mut beta: i32) -> i32 {
-        if depth == 0 {
+    /// Searches `game` `depth` half moves deep, counting mates from `game` itself
+    pub fn search_tree(&self, game: &Game, depth: u16, alpha: i32, beta: i32) -> i32 {
+        self.search_node(&mut game.clone(), depth, 0, alpha, beta)
+    }
+
+    /// search_tree's recursion, making and unmaking each move on the one game. `ply` counts
+    /// the half moves played since the root the search started from, so mates are scored
+    /// by their distance from it
+    fn search_node(&self, game: &mut Game, depth: u16, ply: u16, mut alpha: i32, mut beta: i32) -> i32 {
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+
+        // Checking every leaf for mate and stalemate is too slow for deep searches, but the
+        // leaves of shallow ones sit right below the root where a terminal position scored
+        // on material (a stalemating capture, say) decides the move
+        if self.is_stopped() {
             return self.evaluate_state(game);
         }
+        else if depth == 0 && (ply > 2 || game.has_any_legal_move()) {
+            return if self.use_quiescence { self.quiescence(game, alpha, beta, QUIESCENCE_DEPTH) } else { self.evaluate_state(game) };
+        }
 
-        let moves = game.get_moves();
+        if let Some((value, bound)) = self.probe_transposition_table(game, depth) {
+            match bound {
+                Bound::Exact => return value,
+                Bound::Lower => alpha = cmp::max(alpha, value),
+                Bound::Upper => beta = cmp::min(beta, value),
+            }
+
+            if alpha >= beta {
+                return value;
+            }
+        }
+        let (window_alpha, window_beta) = (alpha, beta);
+
+        // If passing still leaves the side to move past the window after a shallower search,
+        // a real move almost surely does too. Passing isn't safe in check, and with only
+        // pawns left zugzwang makes it better than any move
+        if self.use_null_move && depth > NULL_MOVE_REDUCTION && !game.is_in_check()
+            && game.board.get_pieces(&game.turn).iter().any(|(_, piece_type)| !matches!(piece_type, PieceType::Pawn | PieceType::King)) {
+            let null_value = self.search_node(&mut game.peek_null_move(), depth - 1 - NULL_MOVE_REDUCTION, ply + 1, alpha, beta);
+
+            if game.turn == self.player && null_value > beta {
+                return beta;
+            }
+            else if game.turn != self.player && null_value < alpha {
+                return alpha;
+            }
+        }
+
+        let moves = if depth == 0 { vec!() } else { game.get_moves() };
 
         if moves.is_empty() {
             if game.board.has_check(&game.board.get_king(&game.turn).unwrap(), &game.turn)
             {
                 if self.player == game.turn {
-                    return i32::MIN + (self.search_depth - depth) as i32;
+                    return i32::MIN + ply as i32;
                 } else {
-                    return i32::MAX - (self.search_depth - depth) as i32;
+                    return i32::MAX - ply as i32;
                 }
             } else {
                 return 0;
@@ -151,22 +463,24 @@ impl Engine {
         // Evaluate
         if game.turn == self.player {
             value = i32::MIN;
-            let mut moves_game_list = vec!();
-            
+            let mut moves_list = vec!();
+
             for chess_move in moves.iter() {
-                let mut next_game = game.clone();
-                next_game.make_move(chess_move);
-                let game_eval = self.evaluate_state(&next_game);
-                moves_game_list.push((*chess_move, next_game, game_eval));
+                let undo = game.make_move_undoable(chess_move);
+                moves_list.push((*chess_move, self.evaluate_state(game)));
+                game.unmake_move(undo);
             }
 
-            moves_game_list.sort_unstable_by_key(|(_, _, game_eval)| -*game_eval);
+            self.order_moves(game, ply, &mut moves_list);
 
-            for (chess_move, next_game, _) in moves_game_list.iter() {
-                let castled_bonus = (chess_move == &ChessMove::CastleKingside || chess_move == &ChessMove::CastleQueenside) as i32 * 200;
-                value = cmp::max(value, self.search_tree(next_game, depth - 1, alpha, beta) + castled_bonus);
+            for (chess_move, _) in moves_list.iter() {
+                let undo = game.make_move_undoable(chess_move);
+                let next_value = self.search_node(game, depth - 1, ply + 1, alpha, beta);
+                game.unmake_move(undo);
+                value = cmp::max(value, self.with_castled_bonus(next_value, chess_move, true));
 
                 if value > beta {
+                    self.record_cutoff(game, depth, ply, chess_move);
                     break;
                 }
                 alpha = cmp::max(value, alpha);
@@ -174,22 +488,24 @@ impl Engine {
         } else {
             // min
             value = i32::MAX;
-            let mut moves_game_list = vec!();
-            
+            let mut moves_list = vec!();
+
             for chess_move in moves.iter() {
-                let mut next_game = game.clone();
-                next_game.make_move(chess_move);
-                let game_eval = self.evaluate_state(&next_game);
-                moves_game_list.push((*chess_move, next_game, game_eval));
+                let undo = game.make_move_undoable(chess_move);
+                moves_list.push((*chess_move, self.evaluate_state(game)));
+                game.unmake_move(undo);
             }
 
-            moves_game_list.sort_unstable_by_key(|(_, _, game_eval)| *game_eval);
+            self.order_moves(game, ply, &mut moves_list);
 
-            for (chess_move, next_game, _) in moves_game_list.iter() {
-                let castled_bonus = (chess_move == &ChessMove::CastleKingside || chess_move == &ChessMove::CastleQueenside) as i32 * 200;
-                value = cmp::min(value, self.search_tree(next_game, depth - 1, alpha, beta) - castled_bonus);
+            for (chess_move, _) in moves_list.iter() {
+                let undo = game.make_move_undoable(chess_move);
+                let next_value = self.search_node(game, depth - 1, ply + 1, alpha, beta);
+                game.unmake_move(undo);
+                value = cmp::min(value, self.with_castled_bonus(next_value, chess_move, false));
 
                 if value < alpha {
+                    self.record_cutoff(game, depth, ply, chess_move);
                     break;
                 }
 
@@ -197,37 +513,378 @@ impl Engine {
             }
         }
 
+        self.store_transposition_table(game, depth, value, window_alpha, window_beta);
+
+        value
+    }
+
+    /// Sorts a node's moves so the ones likeliest to cause a cutoff are searched first:
+    /// captures and promotions by mvv_lva, then killer moves, then quiet moves by their
+    /// history score, with the static evaluation of the position after each move
+    /// breaking ties in favour of the side to move
+    fn order_moves(&self, game: &Game, ply: u16, moves_list: &mut [(ChessMove, i32)]) {
+        if !self.use_move_ordering {
+            return;
+        }
+
+        let sign = if game.turn == self.player { -1 } else { 1 };
+
+        if !self.use_history_heuristics {
+            moves_list.sort_unstable_by_key(|(chess_move, game_eval)| (cmp::Reverse(game.mvv_lva(chess_move)), game_eval.saturating_mul(sign)));
+            return;
+        }
+
+        let killers = self.killer_moves.lock().ok().and_then(|killer_moves| killer_moves.get(ply as usize).copied()).unwrap_or_default();
+        let history = match self.history.lock() {
+            Ok(history) => history,
+            Err(_) => return,
+        };
+
+        moves_list.sort_unstable_by_key(|(chess_move, game_eval)| {
+            let is_killer = killers.contains(&Some(*chess_move));
+            let history_score = Engine::history_index(chess_move).map_or(0, |(from, to)| history[from][to]);
+            (cmp::Reverse(game.mvv_lva(chess_move)), cmp::Reverse(is_killer), cmp::Reverse(history_score), game_eval.saturating_mul(sign))
+        });
+    }
+
+    /// Remembers a quiet move which caused a beta cutoff as a killer at its ply and
+    /// raises its history score, more so the deeper the cutoff happened
+    fn record_cutoff(&self, game: &Game, depth: u16, ply: u16, chess_move: &ChessMove) {
+        if !self.use_history_heuristics || game.mvv_lva(chess_move) != 0 {
+            return;
+        }
+
+        let ply = ply as usize;
+        if let Ok(mut killer_moves) = self.killer_moves.lock() {
+            if killer_moves.len() <= ply {
+                killer_moves.resize(ply + 1, [None; 2]);
+            }
+
+            let killers = &mut killer_moves[ply];
+            if killers[0] != Some(*chess_move) {
+                killers[1] = killers[0];
+                killers[0] = Some(*chess_move);
+            }
+        }
+
+        if let (Some((from, to)), Ok(mut history)) = (Engine::history_index(chess_move), self.history.lock()) {
+            history[from][to] = history[from][to].saturating_add(depth as i32 * depth as i32);
+        }
+    }
+
+    /// Gets the from and to square indices of a move in the history table. Castling has none
+    fn history_index(chess_move: &ChessMove) -> Option<(usize, usize)> {
+        match chess_move {
+            ChessMove::Move(from, to) | ChessMove::PawnPromote(from, to, _) => {
+                let ((from_row, from_column), (to_row, to_column)) = (from.decode(), to.decode());
+                Some((from_row * 8 + from_column, to_row * 8 + to_column))
+            }
+            ChessMove::CastleKingside | ChessMove::CastleQueenside => None,
+        }
+    }
+
+    fn clear_move_ordering(&self) {
+        if let Ok(mut killer_moves) = self.killer_moves.lock() {
+            killer_moves.clear();
+        }
+
+        if let Ok(mut history) = self.history.lock() {
+            **history = [[0; 64]; 64];
+        }
+    }
+
+    /// Keeps searching captures and promotions which don't obviously lose material past the
+    /// end of the main search, so a leaf isn't scored halfway through an exchange
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - How many more captures to follow
+    fn quiescence(&self, game: &mut Game, mut alpha: i32, mut beta: i32, depth: u16) -> i32 {
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+        let stand_pat = self.evaluate_state(game);
+        if depth == 0 || self.is_stopped() {
+            return stand_pat;
+        }
+
+        // The side to move can always decline to capture, so the static score bounds the value
+        let is_player_turn = game.turn == self.player;
+        if is_player_turn {
+            if stand_pat > beta {
+                return stand_pat;
+            }
+            alpha = cmp::max(alpha, stand_pat);
+        }
+        else {
+            if stand_pat < alpha {
+                return stand_pat;
+            }
+            beta = cmp::min(beta, stand_pat);
+        }
+
+        let mut value = stand_pat;
+
+        for chess_move in game.loud_moves() {
+            let undo = game.make_move_undoable(&chess_move);
+            let next_value = self.quiescence(game, alpha, beta, depth - 1);
+            game.unmake_move(undo);
+
+            if is_player_turn {
+                value = cmp::max(value, next_value);
+                if value > beta {
+                    break;
+                }
+                alpha = cmp::max(value, alpha);
+            }
+            else {
+                value = cmp::min(value, next_value);
+                if value < alpha {
+                    break;
+                }
+                beta = cmp::min(value, beta);
+            }
+        }
+
         value
     }
 
+    /// Looks up the value and bound of a position searched at least `depth` deep
+    fn probe_transposition_table(&self, game: &Game, depth: u16) -> Option<(i32, Bound)> {
+        if !self.use_transposition_table || depth == 0 {
+            return None;
+        }
+
+        let table = self.transposition_table.lock().ok()?;
+        table.get(&game.zobrist_hash()).filter(|(_, stored_depth, _)| *stored_depth >= depth).map(|&(value, _, bound)| (value, bound))
+    }
+
+    /// Stores a searched position's value along with what the alpha-beta window it was
+    /// searched with proves about it. Leaves aren't stored
+    fn store_transposition_table(&self, game: &Game, depth: u16, value: i32, alpha: i32, beta: i32) {
+        // Mate scores count moves from the root so they don't carry over to other paths,
+        // and a stopped search's value is unfinished
+        if !self.use_transposition_table || depth == 0 || self.is_stopped() || !(-EVAL_BOUND..=EVAL_BOUND).contains(&value) {
+            return;
+        }
+
+        // Cutoffs only happen past the window, so a value on its edge may still be a bound
+        let is_lower = beta != i32::MAX && value >= beta;
+        let is_upper = alpha != i32::MIN && value <= alpha;
+        let bound = match (is_lower, is_upper) {
+            (true, true) => return,
+            (true, false) => Bound::Lower,
+            (false, true) => Bound::Upper,
+            (false, false) => Bound::Exact,
+        };
+
+        if let Ok(mut table) = self.transposition_table.lock() {
+            table.insert(game.zobrist_hash(), (value, depth, bound));
+        }
+    }
+
+    fn clear_transposition_table(&self) {
+        if let Ok(mut table) = self.transposition_table.lock() {
+            table.clear();
+        }
+    }
+
+    /// Makes every evaluation vary by up to `randomness` either way so the engine doesn't
+    /// always play the same game, drawing from a generator seeded with `seed`
+    pub fn set_randomness(&mut self, randomness: i32, seed: u64) {
+        self.randomness = randomness.abs();
+        self.rng = Mutex::new(StdRng::seed_from_u64(seed));
+    }
+
+    /// Gets a handle which stops the running search when set. Each search clears it
+    /// when it starts and, once stopped, answers from the root moves it finished.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Gets how many positions the last search visited, for measuring how well it prunes
+    pub fn nodes_searched(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed)
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
     pub fn get_best_move(&self) -> Option<ChessMove> {
-        let moves = self.game.get_moves();
+        self.get_best_move_with_score().map(|(chess_move, _)| chess_move)
+    }
 
-        let mut returned_move: Option<ChessMove> = None;
-        let mut max_value = i32::MIN;
+    /// Same as get_best_move but also returns the search score of the chosen move
+    pub fn get_best_move_with_score(&self) -> Option<(ChessMove, i32)> {
+        self.search_root(self.search_depth)
+    }
 
-        for chess_move in moves.iter() {
-            let mut next_game = self.game.clone();
-            next_game.make_move(chess_move);
+    /// Whether the engine's side should take a draw, which it does unless a shallow search
+    /// finds it better off than `margin`
+    pub fn should_accept_draw(&self, margin: i32) -> bool {
+        match self.search_root(cmp::min(self.search_depth, 2)) {
+            Some((_, score)) => score <= margin,
+            // With no legal moves the game is already over
+            None => true,
+        }
+    }
+
+    fn search_root(&self, depth: u16) -> Option<(ChessMove, i32)> {
+        self.stop.store(false, Ordering::Relaxed);
+        self.clear_transposition_table();
+        self.clear_move_ordering();
+        self.nodes.store(0, Ordering::Relaxed);
+        let mut best: Option<(ChessMove, i32)> = None;
+        let is_player_turn = self.game.turn == self.player;
+
+        for chess_move in self.legal_moves.iter() {
+            let value = self.evaluate_move(chess_move, depth);
 
-            let value = self.search_tree(&next_game, self.search_depth - 1, i32::MIN, i32::MAX);
+            // The value of a root move whose search was cut short can't be trusted
+            if self.is_stopped() {
+                break;
+            }
 
-            if value > max_value || returned_move.is_none() {
-                max_value = value;
-                returned_move = Some(*chess_move);
+            // Scores are from the engine player's point of view, so the opponent's best move is the lowest
+            if best.is_none_or(|(_, best_value)| if is_player_turn { value > best_value } else { value < best_value }) {
+                best = Some((*chess_move, value));
             }
         }
 
-        returned_move
+        best.or_else(|| self.legal_moves.first().map(|chess_move| (*chess_move, self.evaluate_state(&self.game.peek_move(chess_move)))))
     }
 
+    /// Searches one depth deeper at a time until `budget` runs out, answering with the best
+    /// move of the deepest search that finished. Never searches deeper than 64 half moves
+    pub fn get_best_move_timed(&mut self, budget: Duration) -> Option<ChessMove> {
+        self.search_timed(budget).map(|(chess_move, _, _)| chess_move)
+    }
+
+    /// Same as get_best_move_timed but also returns the search score of the chosen move
+    pub fn get_best_move_timed_with_score(&mut self, budget: Duration) -> Option<(ChessMove, i32)> {
+        self.search_timed(budget).map(|(chess_move, value, _)| (chess_move, value))
+    }
+
+    /// Runs the timed iterative deepening, returning the move, its score and the depth
+    /// it was found at, or depth 0 when not even a one move search finished in time
+    fn search_timed(&mut self, budget: Duration) -> Option<(ChessMove, i32, u16)> {
+        let deadline = Instant::now() + budget;
+        let original_depth = self.search_depth;
+        self.stop.store(false, Ordering::Relaxed);
+        self.clear_transposition_table();
+        self.clear_move_ordering();
+        self.nodes.store(0, Ordering::Relaxed);
+
+        // Stops a root move's search in its tracks once the budget runs out, unless
+        // the deepening finishes first and hangs up
+        let (finished_sender, finished_receiver) = mpsc::channel::<()>();
+        let stop = self.stop.clone();
+
+        let best = thread::scope(|scope| {
+            scope.spawn(move || {
+                if finished_receiver.recv_timeout(budget) == Err(RecvTimeoutError::Timeout) {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            });
+
+            let is_player_turn = self.game.turn == self.player;
+            let mut root_moves: Vec<(ChessMove, i32)> = self.legal_moves.iter().map(|chess_move| (*chess_move, 0)).collect();
+            let mut best: Option<(ChessMove, i32, u16)> = None;
+
+            for depth in 1..=64 {
+                self.search_depth = depth;
+                let mut is_complete = true;
+
+                for (chess_move, value) in root_moves.iter_mut() {
+                    if Instant::now() >= deadline {
+                        is_complete = false;
+                        break;
+                    }
+
+                    *value = self.evaluate_move(chess_move, depth);
+
+                    if self.is_stopped() {
+                        is_complete = false;
+                        break;
+                    }
+                }
+
+                if !is_complete {
+                    break;
+                }
+
+                // The previous depth's best moves are searched first at the next one
+                root_moves.sort_by_key(|(_, value)| if is_player_turn { value.saturating_neg() } else { *value });
+                best = root_moves.first().map(|&(chess_move, value)| (chess_move, value, depth));
+
+                // A forced mate for the side to move won't get any better
+                if best.is_some_and(|(_, value, _)| mate_distance(if is_player_turn { value } else { value.saturating_neg() }).is_some_and(|plies| plies > 0)) {
+                    break;
+                }
+            }
+
+            drop(finished_sender);
+            best
+        });
+
+        self.search_depth = original_depth;
+        best.or_else(|| self.legal_moves.first().map(|chess_move| (*chess_move, self.evaluate_state(&self.game.peek_move(chess_move)), 0)))
+    }
+
+    /// Searches the position after a specific move, so each candidate can be annotated
+    /// with its score rather than only finding the best one
+    ///
+    /// # Arguments
+    ///
+    /// * `chess_move` - A legal move from the engine's current position
+    /// * `depth` - Search depth counting the move itself
+    pub fn evaluate_move(&self, chess_move: &ChessMove, depth: u16) -> i32 {
+        let mut next_game = self.game.peek_move(chess_move);
+        let value = self.search_node(&mut next_game, depth.saturating_sub(1), 1, i32::MIN, i32::MAX);
+        self.with_castled_bonus(value, chess_move, self.game.turn == self.player)
+    }
+
+    /// Adds the castle bonus to the value of the position a move leads to, for the engine's
+    /// side or against the opponent. Mate scores count plies, so they are left as they are
+    fn with_castled_bonus(&self, value: i32, chess_move: &ChessMove, is_player_move: bool) -> i32 {
+        if !chess_move.is_castle() || mate_distance(value).is_some() {
+            return value;
+        }
+
+        let bonus = self.eval_params.castled_bonus;
+        value.saturating_add(if is_player_move { bonus } else { -bonus })
+    }
+
+    /// Plays a book move, picked by weight, when the position is in the opening book
+    /// and otherwise searches in parallel
     pub fn get_best_move_parallel(&self) -> Option<ChessMove> {
+        let book_move = self.opening_book.as_ref().and_then(|book| {
+            let mut rng = self.rng.lock().ok()?;
+            book.pick(&self.game, &mut *rng)
+        });
+
+        if let Some(chess_move) = book_move.filter(|chess_move| self.legal_moves.contains(chess_move)) {
+            debug!("Playing {} from the opening book", chess_move);
+            return Some(chess_move);
+        }
+
+        self.get_best_move_parallel_with_score().map(|(chess_move, _)| chess_move)
+    }
+
+    /// Same as get_best_move_parallel but also returns the search score of the chosen move
+    pub fn get_best_move_parallel_with_score(&self) -> Option<(ChessMove, i32)> {
+        self.stop.store(false, Ordering::Relaxed);
+        self.clear_transposition_table();
+        self.clear_move_ordering();
+        self.nodes.store(0, Ordering::Relaxed);
         let mut next_moves: Vec<(ChessMove, ChessMove, Game)> = vec!();
         let mut move_map: HashMap<ChessMove, i32> = HashMap::new();
 
-        for first_move in self.game.get_moves() {
-            let mut next_game = self.game.clone();
-            next_game.make_move(&first_move);
+        // Scores are from the engine player's point of view, so when the engine is asked for
+        // the opponent's move the root minimizes and the replies maximize instead
+        let is_player_turn = self.game.turn == self.player;
+
+        for &first_move in self.legal_moves.iter() {
+            let next_game = self.game.peek_move(&first_move);
 
             let moves = next_game.get_moves();
             for second_move in moves.iter() {
@@ -237,48 +894,63 @@ impl Engine {
             if moves.is_empty() {
                 if next_game.board.has_check(&next_game.board.get_king(&next_game.turn).unwrap(), &next_game.turn)
                 {
-                    move_map.insert(first_move, i32::MAX);
+                    // Mated one half move from the root, scored like search_tree's mates
+                    move_map.insert(first_move, if is_player_turn { i32::MAX - 1 } else { i32::MIN + 1 });
                 } else {
                     move_map.insert(first_move, 0);
                 }
             }
         }
 
-        let game_lines: Vec<(ChessMove, i32)> = next_moves.par_iter().map(|(first_move, second_move, next_game)| {
-            let castled_bonus = (second_move == &ChessMove::CastleKingside || second_move == &ChessMove::CastleQueenside) as i32 * 200;
-            let mut next_game = next_game.clone();
-            next_game.make_move(second_move);
-            (*first_move, self.search_tree(&next_game, self.search_depth - 2, i32::MIN, i32::MAX) - castled_bonus)
+        let game_lines: Vec<(ChessMove, i32, bool)> = next_moves.par_iter().map(|(first_move, second_move, next_game)| {
+            let mut next_game = next_game.peek_move(second_move);
+            let value = self.with_castled_bonus(self.search_node(&mut next_game, self.search_depth.saturating_sub(2), 2, i32::MIN, i32::MAX), second_move, !is_player_turn);
+            (*first_move, value, !self.is_stopped())
         }).collect();
 
+        // Only root moves whose every reply was searched to completion are compared after a stop
+        let mut incomplete: Vec<ChessMove> = vec!();
 
-        for (chess_move, value) in game_lines.iter() {
-            let castled_bonus = (chess_move == &ChessMove::CastleKingside || chess_move == &ChessMove::CastleQueenside) as i32 * 200;
-            
+        // The root move's own castle bonus is added once the replies are settled, which also
+        // covers the mates and stalemates entered above
+        for &(chess_move, value, is_complete) in game_lines.iter() {
+            if !is_complete {
+                incomplete.push(chess_move);
+            }
 
-            if let Some(min_val) = move_map.get(chess_move) {
-                move_map.insert(*chess_move, cmp::min(*min_val, *value + castled_bonus));
+            // The reply is chosen by the other side
+            if let Some(reply_val) = move_map.get(&chess_move) {
+                move_map.insert(chess_move, if is_player_turn { cmp::min(*reply_val, value) } else { cmp::max(*reply_val, value) });
             }
             else {
-                move_map.insert(*chess_move, *value + castled_bonus);
+                move_map.insert(chess_move, value);
             }
         }
 
-        move_map.drain().max_by_key(|(chess_move, value)| {
-            let castled_bonus = (chess_move == &ChessMove::CastleKingside || chess_move == &ChessMove::CastleQueenside) as i32 * 200;
-            *value + castled_bonus
-        }).map(|(chess_move, _)| chess_move)
+        let scored_moves = move_map.drain().filter(|(chess_move, _)| !incomplete.contains(chess_move)).map(|(chess_move, value)| {
+            (chess_move, self.with_castled_bonus(value, &chess_move, is_player_turn))
+        });
+
+        // The map drains in no fixed order, so equal scores go to the lowest ordered move
+        let best = if is_player_turn {
+            scored_moves.max_by_key(|&(chess_move, value)| (value, cmp::Reverse(chess_move)))
+        } else {
+            scored_moves.min_by_key(|&(chess_move, value)| (value, chess_move))
+        };
+
+        best.or_else(|| self.legal_moves.first().map(|chess_move| (*chess_move, self.evaluate_state(&self.game.peek_move(chess_move)))))
     }
 
     pub fn get_best_move_iterative(&mut self) -> Option<ChessMove> {
         let mut expected_value = 0;
+        let game = self.game.clone();
         for i in 1..=self.search_depth {
             let mut root = self.tree.take().unwrap_or(Node {
                 children: None,
                 value: 0,
                 depth: 0,
             });
-            self.iterative_search(&Game::new(), &mut root, i, i32::MIN, i32::MAX);
+            self.iterative_search(&game, &mut root, i, i32::MIN, i32::MAX);
             expected_value = root.value;
             self.tree = Some(root);
 
@@ -286,7 +958,7 @@ impl Engine {
                 break;
             }
 
-            println!("Searched depth {}", i);
+            debug!("Searched depth {}, expecting {}", i, self.get_principal_variation().iter().map(ChessMove::to_string).collect::<Vec<String>>().join(" "));
         }
 
         self.tree.as_ref().and_then(|root| {
@@ -299,8 +971,58 @@ impl Engine {
         })
     }
 
+    /// Gets the line get_best_move_iterative expects to be played, starting with its best
+    /// move, or nothing before it has searched the current position
+    pub fn get_principal_variation(&self) -> Vec<ChessMove> {
+        let mut line = vec!();
+        let mut node = match self.tree.as_ref() {
+            Some(root) => root,
+            None => return line,
+        };
+
+        // Each node's value came from the first child searched that reached it, and
+        // children cut off after it keep their old values
+        while node.depth > 0 {
+            let best_child = node.children.as_ref().and_then(|children| {
+                children.iter().find(|(_, child)| child.value == node.value && child.depth == node.depth - 1)
+            });
+
+            match best_child {
+                Some((chess_move, child)) => {
+                    line.push(*chess_move);
+                    node = child;
+                },
+                None => break,
+            }
+        }
+
+        line
+    }
+
+    /// Scores every legal move at the engine's depth and returns the best `count` for the
+    /// side to move, best first. Asked after the engine's own move, these are the opponent's
+    /// likeliest replies, lowest scores first since scores are from the engine's side
+    pub fn get_top_moves(&self, count: usize) -> Vec<(ChessMove, i32)> {
+        self.stop.store(false, Ordering::Relaxed);
+        self.clear_transposition_table();
+        self.clear_move_ordering();
+        self.nodes.store(0, Ordering::Relaxed);
+        let is_player_turn = self.game.turn == self.player;
+
+        let mut moves: Vec<(ChessMove, i32)> = self.legal_moves.iter().map(|chess_move| (*chess_move, self.evaluate_move(chess_move, self.search_depth))).collect();
+        moves.sort_by_key(|(_, value)| if is_player_turn { value.saturating_neg() } else { *value });
+        moves.truncate(count);
+        moves
+    }
+
+    /// Gets the cached legal moves for the current position
+    pub fn legal_moves(&self) -> &[ChessMove] {
+        &self.legal_moves
+    }
+
     pub fn advance_move(&mut self, chess_move: ChessMove) {
         self.game.make_move(&chess_move);
+        self.legal_moves = self.game.get_moves();
 
         self.tree = None;
 
@@ -317,54 +1039,11 @@ impl Engine {
     }
 
     pub fn evaluate_state(&self, game: &Game) -> i32 {
-        let mut rng = rand::thread_rng();
-        let mut score = rng.gen_range(-10i32..=10);
-
-        lazy_static! {
-            static ref PAWN_BOARD: [[i32; 8]; 8] = [
-                [100, 100, 100, 100, 100, 100, 100, 100],
-                [100, 100, 100, 100, 100, 100, 100, 100],
-                [100, 100, 115, 115, 115, 115, 100, 100],
-                [105, 105, 120, 120, 120, 120, 105, 105],
-                [120, 120, 120, 120, 120, 120, 120, 120],
-                [120, 120, 120, 120, 120, 120, 120, 120],
-                [120, 120, 120, 120, 120, 120, 120, 120],
-                [100, 100, 100, 100, 100, 100, 100, 100],
-            ];
-            static ref KNIGHT_BOARD: [[i32; 8]; 8] = [
-                [300, 300, 300, 300, 300, 300, 300, 300],
-                [300, 300, 300, 300, 300, 300, 300, 300],
-                [300, 300, 350, 350, 350, 350, 300, 300],
-                [300, 300, 350, 350, 350, 350, 300, 300],
-                [300, 300, 350, 350, 350, 350, 300, 300],
-                [300, 300, 350, 350, 350, 350, 300, 300],
-                [300, 300, 300, 300, 300, 300, 300, 300],
-                [300, 300, 300, 300, 300, 300, 300, 300],
-            ];
-            static ref KING_BOARD: [[i32; 8]; 8] = [
-                [250, 200, 50, 50, 100, 50, 200, 250],
-                [150, 150, 100, 50, 50, 50, 150, 150],
-                [100, 100, 100, 100, 100, 100, 100, 100],
-                [100, 100, 100, 100, 100, 100, 100, 100],
-                [100, 100, 100, 100, 100, 100, 100, 100],
-                [100, 100, 100, 100, 100, 100, 100, 100],
-                [150, 150, 100, 50, 50, 50, 150, 150],
-                [250, 200, 50, 50, 100, 50, 200, 250],
-            ];
-            static ref ENDGAME_KING_BOARD: [[i32; 8]; 8] = [
-                [150, 100, 75, 50, 50, 75, 100, 150],
-                [100, 100, 50, 40, 40, 50, 100, 100],
-                [75, 50, 50, 25, 25, 50, 50, 75],
-                [50, 40, 25, 0, 0, 25, 40, 50],
-                [50, 40, 25, 0, 0, 25, 40, 50],
-                [75, 50, 50, 25, 25, 50, 50, 75],
-                [100, 100, 50, 40, 40, 50, 100, 100],
-                [150, 100, 75, 50, 50, 75, 100, 150],
-            ];
+        let mut score = match self.rng.lock() {
+            Ok(mut rng) if self.randomness != 0 => rng.gen_range(-self.randomness..=self.randomness),
+            _ => 0,
         };
 
-        // TODO: Knights to center of board
-
         // TODO: Pawn positioning
 
         // TODO: Want to maximize threatened squares
@@ -372,68 +1051,78 @@ impl Engine {
         // TODO: Want to push king to corner in endgame
 
         if game.castle_rights[self.player as usize].kingside {
-            score += 50;
+            score += self.eval_params.castle_right_bonus;
         }
 
         if game.castle_rights[self.player as usize].queenside {
-            score += 50;
+            score += self.eval_params.castle_right_bonus;
         }
 
         if game.castle_rights[!self.player as usize].kingside {
-            score -= 25;
+            score -= self.eval_params.opponent_castle_right_penalty;
         }
 
         if game.castle_rights[!self.player as usize].queenside {
-            score -= 25;
+            score -= self.eval_params.opponent_castle_right_penalty;
         }
 
         let mut has_bishup = [false, false];
+        let mut bishup_square_colors = [[false, false], [false, false]];
         let mut has_knight = [false, false];
+        let mut rooks: [Vec<Position>; 2] = [vec!(), vec!()];
 
         for row in 0usize..=7usize {
             for column in 0usize..=7usize {
                 if let Some(piece) = game.board.get(&Position::encode(row, column)) {
                     let piece_value = match piece.piece_type {
                         PieceType::King => {
-                            if game.half_moves < 30 {
-                                KING_BOARD[row][column]
+                            if game.ply() < 30 {
+                                self.eval_params.king_table[row][column]
                             } else {
-                                -ENDGAME_KING_BOARD[row][column]
+                                -self.eval_params.endgame_king_table[row][column]
                             }
                         }
                         PieceType::Queen => {
-                            if game.half_moves < 10 && ((row != 7 && row != 0) || column != 3) {
+                            if game.ply() < 10 && ((row != 7 && row != 0) || column != 3) {
                                 // Don't bring queen out early
-                                800
+                                self.eval_params.early_queen
                             } else {
-                                1000
+                                self.eval_params.queen
                             }
                         }
-                        PieceType::Rook => 500,
+                        PieceType::Rook => {
+                            rooks[piece.color as usize].push(Position::encode(row, column));
+                            self.eval_params.rook
+                        }
                         PieceType::Bishup => {
-                            let bishup_value = if has_bishup[piece.color as usize] {
-                                525
+                            // The pair is only worth more when the bishops cover both square colors
+                            let square_color = Position::encode(row, column).square_color();
+                            let bishup_value = if bishup_square_colors[piece.color as usize][!square_color as usize] {
+                                self.eval_params.bishup + self.eval_params.bishup_pair_bonus
                             } else {
-                                425
+                                self.eval_params.bishup
                             };
+                            bishup_square_colors[piece.color as usize][square_color as usize] = true;
                             has_bishup[piece.color as usize] = !has_bishup[piece.color as usize];
                             bishup_value
                         }
                         PieceType::Knight => {
+                            let outpost_bonus = if Engine::is_knight_outpost(&game.board, &Position::encode(row, column), &piece.color) {
+                                self.eval_params.knight_outpost_bonus
+                            } else {
+                                0
+                            };
                             let knight_value = if has_knight[piece.color as usize] {
-                                KNIGHT_BOARD[row][column] + 200
+                                self.eval_params.knight_table[row][column] + outpost_bonus + self.eval_params.knight + self.eval_params.knight_pair_bonus
                             } else {
-                                KNIGHT_BOARD[row][column] + 100
+                                self.eval_params.knight_table[row][column] + outpost_bonus + self.eval_params.knight
                             };
                             has_knight[piece.color as usize] = !has_bishup[piece.color as usize];
                             knight_value
                         }
                         PieceType::Pawn => {
                             // Pawns get more valuable as game goes on
-                            match self.player {
-                                PieceColor::Black => PAWN_BOARD[7 - row][column] + game.half_moves as i32 * 2,
-                                PieceColor::White => PAWN_BOARD[row][column] + game.half_moves as i32 * 2,
-                            }                           
+                            self.eval_params.pawn_table[Position::encode(row, column).relative_rank(&piece.color)][column] + game.ply() as i32 * self.eval_params.pawn_growth
                         }
                     };
 
@@ -446,6 +1135,127 @@ impl Engine {
             }
         }
 
+        score += self.evaluate_rooks(&game.board, &self.player, &rooks[self.player as usize]);
+        score -= self.evaluate_rooks(&game.board, &!self.player, &rooks[!self.player as usize]);
+
+        if self.eval_params.king_attack_bonus != 0 || self.eval_params.pawn_storm_bonus != 0 {
+            score += self.evaluate_king_attack(&game.board, &self.player);
+            score -= self.evaluate_king_attack(&game.board, &!self.player);
+        }
+
+        score.clamp(-EVAL_BOUND, EVAL_BOUND)
+    }
+
+    /// Scores the side's pieces attacking the squares around the enemy king
+    /// and its pawns advancing on the king's file and the files beside it
+    fn evaluate_king_attack(&self, board: &Board, player_color: &PieceColor) -> i32 {
+        let enemy_king = match board.get_king(&!*player_color) {
+            Some(king) => king,
+            None => return 0,
+        };
+
+        let king_zone: Vec<Position> = enemy_king.neighbors().collect();
+        let mut attackers = vec!();
+        board.for_each_attack(player_color, |attacker, target| {
+            if king_zone.contains(&target) && !attackers.contains(&attacker) && board.get(&attacker).is_some_and(|piece| piece.piece_type != PieceType::King) {
+                attackers.push(attacker);
+            }
+        });
+
+        let pawn_storm: usize = board.get_pieces(player_color).iter()
+            .filter(|(position, piece_type)| *piece_type == PieceType::Pawn && position.column().abs_diff(enemy_king.column()) <= 1)
+            .map(|(position, _)| position.relative_rank(player_color).saturating_sub(1))
+            .sum();
+
+        attackers.len() as i32 * self.eval_params.king_attack_bonus + pawn_storm as i32 * self.eval_params.pawn_storm_bonus
+    }
+
+    /// Whether a knight sits in the opponent's half, defended by one of its own pawns,
+    /// where no enemy pawn can ever advance to attack it
+    fn is_knight_outpost(board: &Board, position: &Position, player_color: &PieceColor) -> bool {
+        if !(4..=6).contains(&position.relative_rank(player_color)) {
+            return false;
+        }
+
+        let (row, column) = position.decode_isize();
+        let behind = match player_color {
+            PieceColor::White => row - 1,
+            PieceColor::Black => row + 1,
+        };
+
+        let is_defended = [column - 1, column + 1].iter().any(|&defender_column| {
+            Position::encode_checked(behind, defender_column).is_some_and(|defender| {
+                board.get(&defender).is_some_and(|&Piece{piece_type, color}| piece_type == PieceType::Pawn && color == *player_color)
+            })
+        });
+
+        // An enemy pawn on an adjacent file in front of the knight could still be pushed to attack it
+        let rows_ahead = match player_color {
+            PieceColor::White => (row + 1)..8,
+            PieceColor::Black => 0..row,
+        };
+
+        let can_be_kicked = rows_ahead.into_iter().any(|search_row| {
+            [column - 1, column + 1].iter().any(|&search_column| {
+                Position::encode_checked(search_row, search_column).is_some_and(|search| {
+                    board.get(&search).is_some_and(|&Piece{piece_type, color}| piece_type == PieceType::Pawn && color != *player_color)
+                })
+            })
+        });
+
+        is_defended && !can_be_kicked
+    }
+
+    /// Scores rooks on open files, doubled on a file, connected on the back rank
+    /// and behind their own passed pawns
+    ///
+    /// # Arguments
+    ///
+    /// * `rooks` - Positions of all of `player_color`'s rooks
+    fn evaluate_rooks(&self, board: &Board, player_color: &PieceColor, rooks: &[Position]) -> i32 {
+        let mut score = 0;
+
+        for rook in rooks.iter() {
+            score += match board.file_status(rook.column(), player_color) {
+                FileStatus::Open => self.eval_params.rook_open_file_bonus,
+                FileStatus::HalfOpen => self.eval_params.rook_half_open_file_bonus,
+                FileStatus::Closed => 0,
+            };
+
+            let rows_ahead = match player_color {
+                PieceColor::White => (rook.row() + 1)..8,
+                PieceColor::Black => 0..rook.row(),
+            };
+
+            let is_behind_passed_pawn = rows_ahead.map(|row| Position::encode(row, rook.column())).any(|position| {
+                board.get(&position).is_some_and(|&Piece{piece_type, color}| piece_type == PieceType::Pawn && color == *player_color) && board.is_passed_pawn(&position, player_color)
+            });
+
+            if is_behind_passed_pawn {
+                score += self.eval_params.rook_behind_passed_pawn_bonus;
+            }
+        }
+
+        let back_row = match player_color {
+            PieceColor::White => 0,
+            PieceColor::Black => 7,
+        };
+
+        for (index, first) in rooks.iter().enumerate() {
+            for second in rooks.iter().skip(index + 1) {
+                if first.column() == second.column() {
+                    score += self.eval_params.doubled_rooks_bonus;
+                }
+
+                if first.row() == back_row && second.row() == back_row {
+                    let (left, right) = (cmp::min(first.column(), second.column()), cmp::max(first.column(), second.column()));
+                    if ((left + 1)..right).all(|column| board.get(&Position::encode(back_row, column)).is_none()) {
+                        score += self.eval_params.connected_rooks_bonus;
+                    }
+                }
+            }
+        }
+
         score
     }
 }
@@ -457,17 +1267,475 @@ mod tests {
     fn get_engine_with_moves(moves_list: Vec<&str>) -> Engine {
         let mut engine = Engine::new(Game::new(), PieceColor::White, 5);
 
+        // Reading moves against the position turns coordinate castling like e1g1 into the castle
         for move_text in moves_list.iter() {
-            if let Some(chess_move) = ChessMove::from_str(move_text) {
-                engine.advance_move(chess_move);
-            } else {
-                println!("Could not parse move from string {}", move_text);
-            }
+            let chess_move = engine.game.parse_move(move_text).expect("Could not parse move");
+            assert!(engine.legal_moves().contains(&chess_move), "{} is illegal in {}", move_text, engine.game.to_fen());
+            engine.advance_move(chess_move);
         }
 
         engine
     }
 
+    #[test]
+    fn test_legal_moves_cache() {
+        let mut engine = get_engine_with_moves(vec!["e2e4", "e7e5"]);
+        assert!(engine.legal_moves() == engine.game.get_moves().as_slice());
+
+        engine.advance_move(ChessMove::from_str("g1f3").expect(""));
+        assert!(engine.legal_moves() == engine.game.get_moves().as_slice());
+        assert!(engine.legal_moves().iter().all(|chess_move| chess_move.to_string() != "g1f3"));
+    }
+
+    #[test]
+    fn test_evaluation_is_bounded() {
+        let mut game = Game::from_fen("QQQQKQQQ/PPPPPPPP/8/8/8/8/8/7k w - - 0 1").expect("");
+        game.full_moves = u16::MAX;
+
+        let engine = Engine::new(game.clone(), PieceColor::White, 2);
+        assert!(engine.evaluate_state(&game) == EVAL_BOUND);
+
+        let engine = Engine::new(game.clone(), PieceColor::Black, 2);
+        assert!(engine.evaluate_state(&game) == -EVAL_BOUND);
+    }
+
+    fn evaluate_white_rooks(fen: &str) -> i32 {
+        let game = Game::from_fen(fen).expect("");
+        let rooks: Vec<Position> = game.board.get_pieces(&PieceColor::White).iter().filter(|(_, piece_type)| *piece_type == PieceType::Rook).map(|(position, _)| *position).collect();
+        Engine::new(game.clone(), PieceColor::White, 2).evaluate_rooks(&game.board, &PieceColor::White, &rooks)
+    }
+
+    #[test]
+    fn test_evaluate_rooks() {
+        let doubled = evaluate_white_rooks("6k1/pp3ppp/8/8/8/8/PP1R1PPP/3R2K1 w - - 0 1");
+        let scattered = evaluate_white_rooks("6k1/pp3ppp/8/8/8/7R/RP3PPP/6K1 w - - 0 1");
+        assert!(doubled > scattered);
+
+        let connected = evaluate_white_rooks("r5k1/8/8/8/8/8/8/R4RK1 w - - 0 1");
+        let blocked = evaluate_white_rooks("r5k1/8/8/8/8/8/8/R1B2RK1 w - - 0 1");
+        assert!(connected - blocked == EvalParams::standard().connected_rooks_bonus);
+
+        let behind_passer = evaluate_white_rooks("6k1/8/8/3P4/8/8/8/3R2K1 w - - 0 1");
+        let behind_blocked = evaluate_white_rooks("6k1/4p3/8/3P4/8/8/8/3R2K1 w - - 0 1");
+        assert!(behind_passer - behind_blocked == EvalParams::standard().rook_behind_passed_pawn_bonus);
+    }
+
+    #[test]
+    fn test_stop_flag_ends_search() {
+        for parallel in [false, true] {
+            let engine = Engine::new(Game::new(), PieceColor::White, 9);
+            let stop = engine.stop_flag();
+
+            let stopper = std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                stop.store(true, Ordering::Relaxed);
+            });
+
+            let started = std::time::Instant::now();
+            let chess_move = if parallel { engine.get_best_move_parallel() } else { engine.get_best_move() };
+            stopper.join().expect("");
+
+            assert!(started.elapsed() < std::time::Duration::from_secs(10));
+            assert!(chess_move.is_some_and(|chess_move| engine.legal_moves().contains(&chess_move)));
+        }
+    }
+
+    #[test]
+    fn test_bishup_pair_needs_both_square_colors() {
+        let engine = Engine::new(Game::new(), PieceColor::White, 2);
+        let pair = Game::from_fen("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1").expect("");
+        let same_color = Game::from_fen("4k3/8/8/8/8/8/8/2B1K1B1 w - - 0 1").expect("");
+
+        assert!(engine.evaluate_state(&pair) - engine.evaluate_state(&same_color) == EvalParams::standard().bishup_pair_bonus);
+    }
+
+    #[test]
+    fn test_evaluate_move() {
+        let engine = Engine::new(Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").expect(""), PieceColor::White, 3);
+        let (best_move, best_score) = engine.get_best_move_with_score().expect("");
+
+        assert!(best_move == ChessMove::from_str("a1a8").expect(""));
+        assert!(engine.evaluate_move(&best_move, 3) == best_score);
+        assert!(engine.evaluate_move(&ChessMove::from_str("a1a7").expect(""), 3) < best_score);
+
+        // Mates and stalemates are found at the given depth, not the engine's own
+        let engine = Engine::new(engine.game.clone(), PieceColor::White, 5);
+        for depth in [1, 2, 3] {
+            assert!(mate_distance(engine.evaluate_move(&best_move, depth)) == Some(1));
+        }
+
+        let engine = Engine::new(Game::from_fen("7k/5K2/6r1/8/8/8/8/6Q1 w - - 0 1").expect(""), PieceColor::White, 4);
+        assert!(engine.evaluate_move(&ChessMove::from_str("g1g6").expect(""), 1) == 0);
+    }
+
+    #[test]
+    fn test_iterative_agrees_with_search() {
+        // A back rank mate, a hanging queen and a knight fork of king and rook
+        for fen in ["6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "6k1/5ppp/8/3q4/8/8/3R4/6K1 w - - 0 30", "4r1k1/5p1p/6p1/8/4N3/8/5PPP/6K1 w - - 0 30"] {
+            let game = Game::from_fen(fen).expect("");
+            let mut engine = Engine::new(game.clone(), game.turn, 3);
+
+            let best_move = engine.get_best_move();
+            assert!(best_move.is_some());
+            assert!(engine.get_best_move_iterative() == best_move, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_principal_variation() {
+        let engine = Engine::new(Game::new(), PieceColor::White, 3);
+        assert!(engine.get_principal_variation().is_empty());
+
+        for fen in ["7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", "4r1k1/5p1p/6p1/8/4N3/8/5PPP/6K1 w - - 0 30", crate::game::START_FEN] {
+            let game = Game::from_fen(fen).expect("");
+            let mut engine = Engine::new(game.clone(), game.turn, 4);
+            let best_move = engine.get_best_move_iterative();
+
+            let line = engine.get_principal_variation();
+            assert!(!line.is_empty() && line.len() <= 4, "{}", fen);
+            assert!(line.first() == best_move.as_ref(), "{}", fen);
+
+            let mut game = game.clone();
+            for chess_move in line.iter() {
+                assert!(game.get_moves().contains(chess_move), "{} is illegal in {}", chess_move, game.to_fen());
+                game.make_move(chess_move);
+            }
+        }
+
+        // Ra7 cuts the king off and Rb8 mates, so the line ends at the mate
+        let game = Game::from_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1").expect("");
+        let mut engine = Engine::new(game, PieceColor::White, 4);
+        engine.get_best_move_iterative();
+        assert!(engine.get_principal_variation().len() == 3);
+    }
+
+    #[test]
+    fn test_get_best_move_timed() {
+        let game = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").expect("");
+        let mut engine = Engine::new(game.clone(), game.turn, 3);
+
+        let chess_move = engine.get_best_move_timed(Duration::ZERO);
+        assert!(chess_move.is_some_and(|chess_move| engine.legal_moves().contains(&chess_move)));
+
+        let started = Instant::now();
+        let (_, _, depth) = engine.search_timed(Duration::from_secs(2)).expect("");
+        assert!(depth > 1);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(engine.search_depth == 3);
+
+        // The mate is found at depth 1 and deepening stops there
+        let mut engine = Engine::new(Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").expect(""), PieceColor::White, 3);
+        assert!(engine.search_timed(Duration::from_secs(10)).is_some_and(|(chess_move, _, depth)| chess_move.to_string() == "a1a8" && depth == 1));
+        assert!(engine.get_best_move_timed_with_score(Duration::from_secs(10)).is_some_and(|(_, value)| mate_distance(value) == Some(1)));
+    }
+
+    #[test]
+    fn test_get_top_moves() {
+        // White has just played, so the top moves are black's replies
+        let game = Game::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR b KQkq - 3 3").expect("");
+        let engine = Engine::new(game, PieceColor::White, 2);
+
+        let replies = engine.get_top_moves(5);
+        assert!(replies.len() == 5);
+        assert!(replies.iter().all(|(chess_move, value)| engine.legal_moves().contains(chess_move) && *value == engine.evaluate_move(chess_move, 2)));
+        assert!(replies.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+
+        // Only a move that stops Qxf7# keeps black from being mated
+        assert!(mate_distance(replies[0].1).is_none());
+        assert!(engine.get_top_moves(100).last().is_some_and(|(_, value)| mate_distance(*value).is_some_and(|plies| plies > 0)));
+        assert!(engine.get_top_moves(100).len() == engine.legal_moves().len());
+
+        // From the engine's own side the best move comes first
+        let engine = Engine::new(Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").expect(""), PieceColor::White, 2);
+        assert!(engine.get_top_moves(3).first().is_some_and(|(chess_move, _)| chess_move.to_string() == "a1a8"));
+    }
+
+    #[test]
+    fn test_quiescence() {
+        // Qxd5 wins a pawn, but e6 takes the queen back
+        let game = Game::from_fen("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 30").expect("");
+        let hanging_capture = ChessMove::from_str("d1d5").expect("");
+
+        let mut engine = Engine::new(game.clone(), PieceColor::White, 1);
+        engine.use_quiescence = false;
+        assert!(engine.evaluate_move(&hanging_capture, 1) > engine.evaluate_state(&game));
+        assert!(engine.get_best_move() == Some(hanging_capture));
+
+        engine.use_quiescence = true;
+        assert!(engine.evaluate_move(&hanging_capture, 1) < 0);
+        assert!(engine.get_best_move().is_some_and(|chess_move| chess_move != hanging_capture));
+    }
+
+    #[test]
+    fn test_move_ordering_prunes_more() {
+        let mut engine = Engine::new(Game::new(), PieceColor::White, 4);
+        let ordered = engine.get_best_move();
+        let ordered_nodes = engine.nodes_searched();
+
+        engine.use_move_ordering = false;
+        let unordered = engine.get_best_move();
+        let unordered_nodes = engine.nodes_searched();
+
+        assert!(ordered.is_some() && unordered.is_some());
+        assert!(ordered_nodes > 0 && ordered_nodes < unordered_nodes);
+    }
+
+    #[test]
+    fn test_history_heuristics_prune_more() {
+        // Rooks and pawns with nothing to take, so nearly every move is ordered by the heuristics
+        let game = Game::from_fen("r4rk1/1p3ppp/p1p1p3/8/3P4/2P1P3/P4PPP/R4RK1 w - - 0 20").expect("");
+        let mut engine = Engine::new(game, PieceColor::White, 5);
+        // Quiescence only searches captures, which the heuristics don't order
+        engine.use_quiescence = false;
+        let with_heuristics = engine.get_best_move();
+        let with_heuristics_nodes = engine.nodes_searched();
+
+        engine.use_history_heuristics = false;
+        let without_heuristics = engine.get_best_move();
+        let without_heuristics_nodes = engine.nodes_searched();
+
+        assert!(with_heuristics.is_some() && with_heuristics == without_heuristics);
+        assert!(with_heuristics_nodes < without_heuristics_nodes);
+    }
+
+    #[test]
+    fn test_null_move_keeps_mate() {
+        // Kf7 leaves the black king only h7, then Rh1 mates. The quiet first move is
+        // the kind a null move search could wrongly prune
+        let game = Game::from_fen("7k/8/5K2/8/8/8/8/6R1 w - - 0 1").expect("");
+
+        for use_null_move in [true, false] {
+            let mut engine = Engine::new(game.clone(), PieceColor::White, 4);
+            engine.use_null_move = use_null_move;
+            assert!(engine.get_best_move_with_score().is_some_and(|(chess_move, score)| chess_move == ChessMove::from_str("f6f7").expect("") && mate_distance(score) == Some(3)));
+            assert!(engine.get_best_move_parallel_with_score().is_some_and(|(_, score)| mate_distance(score) == Some(3)));
+        }
+    }
+
+    #[test]
+    fn test_mate_scores() {
+        let engine = Engine::new(Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").expect(""), PieceColor::White, 3);
+        assert!(engine.get_best_move_with_score().is_some_and(|(_, score)| mate_distance(score) == Some(1)));
+        assert!(engine.get_best_move_parallel_with_score().is_some_and(|(_, score)| mate_distance(score) == Some(1)));
+
+        // Ra7 cuts the king off and Rb8 mates, three half moves in
+        let engine = Engine::new(Game::from_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1").expect(""), PieceColor::White, 4);
+        assert!(engine.get_best_move_with_score().is_some_and(|(_, score)| mate_distance(score) == Some(3)));
+        assert!(engine.get_best_move_parallel_with_score().is_some_and(|(_, score)| mate_distance(score) == Some(3)));
+
+        // The black engine is mated by either of white's back rank moves
+        let engine = Engine::new(Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").expect(""), PieceColor::Black, 3);
+        assert!(engine.get_best_move_with_score().is_some_and(|(_, score)| mate_distance(score) == Some(-1)));
+
+        // Castling mates at once, and the castle bonus mustn't count as a ply nearer
+        let engine = Engine::new(Game::from_fen("r3k3/8/8/8/8/8/2P1P3/2RKR3 b q - 0 1").expect(""), PieceColor::Black, 2);
+        assert!(engine.get_best_move_parallel_with_score().is_some_and(|(chess_move, score)| chess_move == ChessMove::CastleQueenside && mate_distance(score) == Some(1)));
+        assert!(engine.get_best_move_with_score().is_some_and(|(_, score)| mate_distance(score) == Some(1)));
+
+        assert!(mate_distance(EVAL_BOUND).is_none() && mate_distance(-900).is_none());
+    }
+
+    #[test]
+    fn test_castle_bonus_counted_once() {
+        // Castling gets the bonus once at the root whichever search scores it
+        let game = Game::from_fen("4k3/8/8/8/8/8/5PPP/4K2R w K - 0 1").expect("");
+
+        for search_depth in [2, 3] {
+            let engine = Engine::new(game.clone(), PieceColor::White, search_depth);
+            let sequential = engine.get_best_move_with_score().expect("");
+
+            assert!(sequential.0 == ChessMove::CastleKingside);
+            assert!(engine.get_best_move_parallel_with_score() == Some(sequential));
+            assert!(engine.evaluate_move(&ChessMove::CastleKingside, search_depth) == sequential.1);
+        }
+    }
+
+    #[test]
+    fn test_stalemate_is_scored_as_draw() {
+        // Qxg6 wins the rook but leaves black with no legal moves
+        let game = Game::from_fen("7k/5K2/6r1/8/8/8/8/6Q1 w - - 0 1").expect("");
+        let stalemating_capture = ChessMove::from_str("g1g6").expect("");
+
+        for search_depth in [1, 2] {
+            let engine = Engine::new(game.clone(), PieceColor::White, search_depth);
+            assert!(engine.evaluate_move(&stalemating_capture, search_depth) == 0);
+            assert!(engine.get_best_move() != Some(stalemating_capture));
+            assert!(engine.get_best_move_parallel() != Some(stalemating_capture));
+        }
+    }
+
+    #[test]
+    fn test_perspective_on_opponents_turn() {
+        // Symmetric positions are level for either player, whoever is to move. Castling
+        // rights are left out as they're deliberately weighted more for the engine's side
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b - - 0 1"] {
+            let game = Game::from_fen(fen).expect("");
+            for player in [PieceColor::White, PieceColor::Black] {
+                assert!(Engine::new(game.clone(), player, 2).evaluate_state(&game) == 0);
+            }
+        }
+
+        // White is a queen up with black to move, so a white engine still sees a winning score
+        let engine = Engine::new(Game::from_fen("3qk3/8/8/8/8/8/8/3QKQ2 b - - 0 1").expect(""), PieceColor::White, 2);
+        assert!(engine.evaluate_state(&engine.game) > 500);
+        assert!(engine.get_best_move_with_score().is_some_and(|(_, score)| score > 500));
+
+        // Asked for black's move, a white engine finds black's mate rather than the move best for white
+        let engine = Engine::new(Game::from_fen("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1").expect(""), PieceColor::White, 3);
+        let mate = ChessMove::from_str("a8a1");
+        assert!(engine.get_best_move() == mate);
+        assert!(engine.get_best_move_parallel() == mate);
+        assert!(engine.get_best_move_with_score().is_some_and(|(_, score)| score < -EVAL_BOUND));
+    }
+
+    #[test]
+    fn test_decision_thresholds() {
+        let thresholds = DecisionThresholds { resign: Some(-900), draw: Some(-200), moves: 3 };
+        let scores = [50, -300, -950, -1000, -400, -1200, -950, -2000];
+        let decisions: Vec<GameDecision> = (1..=scores.len()).map(|played| thresholds.decide(&scores[..played])).collect();
+
+        use GameDecision::*;
+        assert_eq!(decisions, vec![Continue, Continue, Continue, AcceptDraw, AcceptDraw, AcceptDraw, AcceptDraw, Resign]);
+
+        let disabled = DecisionThresholds { resign: None, draw: None, moves: 1 };
+        assert!(disabled.decide(&scores) == Continue);
+    }
+
+    #[test]
+    fn test_should_accept_draw() {
+        let winning = Game::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").expect("");
+        assert!(!Engine::new(winning.clone(), PieceColor::White, 4).should_accept_draw(50));
+        assert!(Engine::new(winning, PieceColor::Black, 4).should_accept_draw(50));
+
+        let drawn = Game::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").expect("");
+        assert!(Engine::new(drawn, PieceColor::White, 4).should_accept_draw(50));
+    }
+
+    #[test]
+    fn test_knight_placement() {
+        let engine = Engine::new(Game::new(), PieceColor::White, 2);
+        let central = Game::from_fen("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1").expect("");
+        let rim = Game::from_fen("4k3/8/8/8/N7/8/8/4K3 w - - 0 1").expect("");
+
+        // 50 points apart in the table
+        assert!(engine.evaluate_state(&central) - engine.evaluate_state(&rim) == 50);
+
+        let outpost = Game::from_fen("4k3/pp4pp/8/3N4/4P3/8/8/4K3 w - - 0 1").expect("");
+        assert!(Engine::is_knight_outpost(&outpost.board, &Position::from_str("d5").expect(""), &PieceColor::White));
+
+        let kickable = Game::from_fen("4k3/pp2p1pp/8/3N4/4P3/8/8/4K3 w - - 0 1").expect("");
+        assert!(!Engine::is_knight_outpost(&kickable.board, &Position::from_str("d5").expect(""), &PieceColor::White));
+
+        let undefended = Game::from_fen("4k3/pp4pp/8/3N4/8/4P3/8/4K3 w - - 0 1").expect("");
+        assert!(!Engine::is_knight_outpost(&undefended.board, &Position::from_str("d5").expect(""), &PieceColor::White));
+    }
+
+    #[test]
+    fn test_eval_presets() {
+        // The queen can take either the rook on a6 or the knight on e5
+        let game = Game::from_fen("7k/8/r7/4n3/8/8/4Q3/6K1 w - - 0 1").expect("");
+
+        let standard = Engine::new(game.clone(), PieceColor::White, 2);
+        assert!(standard.get_best_move_parallel() == ChessMove::from_str("e2a6"));
+
+        let mut aggressive = Engine::new(game.clone(), PieceColor::White, 2);
+        aggressive.eval_params = EvalParams::aggressive();
+        assert!(aggressive.get_best_move_parallel() == ChessMove::from_str("e2e5"));
+    }
+
+    #[test]
+    fn test_custom_piece_square_tables() {
+        let game = Game::from_fen("4k3/8/8/8/3P4/8/8/4K3 w - - 0 30").expect("");
+        let standard = Engine::new(game.clone(), PieceColor::White, 2);
+
+        // Push the d pawn's square on the fourth rank up by 50
+        let mut eval_params = EvalParams::standard();
+        eval_params.pawn_table[3][3] += 50;
+        let custom = Engine::with_eval_params(game.clone(), PieceColor::White, 2, eval_params);
+        assert!(custom.eval_params == eval_params);
+        assert!(custom.evaluate_state(&game) == standard.evaluate_state(&game) + 50);
+
+        // Black's pawns read the table from their own side of the board
+        let mirrored = Game::from_fen("4k3/8/8/3p4/8/8/8/4K3 w - - 0 30").expect("");
+        assert!(custom.evaluate_state(&mirrored) == standard.evaluate_state(&mirrored) - 50);
+    }
+
+    #[test]
+    fn test_opening_book() {
+        let mut engine = Engine::new(Game::new(), PieceColor::White, 3);
+        engine.opening_book = Some(OpeningBook::from_str("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1; b2b3:1, g1f3:1").expect(""));
+
+        for _ in 0..10 {
+            let chess_move = engine.get_best_move_parallel().expect("").to_string();
+            assert!(chess_move == "b2b3" || chess_move == "g1f3");
+        }
+
+        // Out of the book the engine searches as usual
+        engine.advance_move(ChessMove::from_str("e2e4").expect(""));
+        assert!(engine.get_best_move_parallel().is_some_and(|chess_move| engine.legal_moves().contains(&chess_move)));
+    }
+
+    #[test]
+    fn test_king_hunt() {
+        // The bishop can take the loose pawn on a6 or give itself up on h7 to drag
+        // the king out to where the knight, queen and h pawn can reach it
+        let game = Game::from_fen("4r1k1/5ppp/p7/7P/7N/3B4/5PP1/2Q3K1 w - - 0 20").expect("");
+
+        // Compare the weights alone at the horizon rather than after quiescence resolves the captures
+        let mut standard = Engine::new(game.clone(), PieceColor::White, 2);
+        standard.use_quiescence = false;
+        assert!(standard.get_best_move_parallel() == ChessMove::from_str("d3a6"));
+
+        let mut king_hunt = Engine::new(game.clone(), PieceColor::White, 2);
+        king_hunt.use_quiescence = false;
+        king_hunt.eval_params = EvalParams::king_hunt();
+        assert!(king_hunt.get_best_move_parallel() == ChessMove::from_str("d3h7"));
+    }
+
+    #[test]
+    fn test_transposition_table_keeps_best_move() {
+        // A back rank mate, a hanging queen and a knight fork of king and rook
+        let cases = [
+            ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"),
+            ("6k1/5ppp/8/3q4/8/8/3R4/6K1 w - - 0 30", "d2d5"),
+            ("4r1k1/5p1p/6p1/8/4N3/8/5PPP/6K1 w - - 0 30", "e4f6"),
+        ];
+
+        for (fen, best_move) in cases {
+            let game = Game::from_fen(fen).expect("");
+
+            for use_transposition_table in [false, true] {
+                let mut engine = Engine::new(game.clone(), game.turn, 3);
+                engine.use_transposition_table = use_transposition_table;
+
+                assert!(engine.get_best_move() == ChessMove::from_str(best_move), "{}", fen);
+                assert!(engine.get_best_move_parallel() == ChessMove::from_str(best_move), "{}", fen);
+            }
+        }
+    }
+
+    #[test]
+    fn test_castling_into_mate() {
+        // O-O-O mates, and taking the castle bonus off the mate score must not overflow
+        let game = Game::from_fen("r3k3/8/8/8/8/8/2P1P3/2RKR3 b q - 0 1").expect("");
+        let engine = Engine::new(game.clone(), PieceColor::White, 2);
+        assert!(mate_distance(engine.search_tree(&game, 2, i32::MIN, i32::MAX)) == Some(-1));
+    }
+
+    #[test]
+    fn test_evaluate_state_deterministic() {
+        let game = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").expect("");
+        let mut engine = Engine::new(game.clone(), PieceColor::White, 3);
+        assert!(engine.evaluate_state(&game) == engine.evaluate_state(&game));
+
+        engine.set_randomness(10, 7);
+        let first: Vec<i32> = (0..20).map(|_| engine.evaluate_state(&game)).collect();
+        engine.set_randomness(10, 7);
+        let second: Vec<i32> = (0..20).map(|_| engine.evaluate_state(&game)).collect();
+        assert!(first == second);
+        assert!(first.iter().any(|&score| score != first[0]));
+    }
+
     #[test]
     fn test_could_not_find_king_0() {
         let moves_list = vec![
@@ -578,9 +1846,14 @@ mod tests {
         ];
 
         let engine = get_engine_with_moves(moves_list);
-        println!("Got to FEN {}", engine.game.to_fen());
-        let moves = engine.game.get_moves();
-        assert!(!moves.is_empty());
+        assert!(engine.game.to_fen() == "8/1p3k2/6p1/2p5/7q/3p1P2/PB2r2P/N4K1R w - - 4 45");
+
+        // Not in check, and the king's only safe square is g1: e1 is on the queen's
+        // diagonal, f2 and g2 are on the rook's rank and the d3 pawn guards e2
+        let mut moves: Vec<String> = engine.game.get_moves().iter().map(ChessMove::to_string).collect();
+        moves.sort();
+        assert!(moves == ["a1b3", "a1c2", "a2a3", "a2a4", "b2a3", "b2c1", "b2c3", "b2d4", "b2e5", "b2f6", "b2g7", "b2h8", "f1g1", "f3f4", "h1g1", "h2h3"]);
+        assert!(engine.game.perft(1) == 16);
     }
 
     #[test]
@@ -593,19 +1866,34 @@ mod tests {
         ];
 
         let engine = get_engine_with_moves(moves_list);
-        println!("Got to FEN {}", engine.game.to_fen());
-        let moves = engine.game.get_moves();
-        assert!(!moves.is_empty());
+        assert!(engine.game.to_fen() == "1nq5/3pkBb1/5pP1/Q3pn2/7p/BP2PN2/P1PP1P1P/R3K2R b KQ - 2 19");
+
+        // The a3 bishop checks along the diagonal and every king move is covered, the f7
+        // bishop by the g6 pawn, so the only ways out are the three blocks on d6 and c5
+        let mut moves: Vec<String> = engine.game.get_moves().iter().map(ChessMove::to_string).collect();
+        moves.sort();
+        assert!(moves == ["c8c5", "d7d6", "f5d6"]);
+        assert!(engine.game.perft(1) == 3);
+        assert!(engine.game.status() == crate::game::GameResult::Ongoing);
     }
 
     #[test]
     fn test_failed_move() {
-        let moves_list = vec![
+        // This game was recorded with castling split into the king's move and a rook move
+        let moves_list = [
             "b2b3", "g7g6", "c1b2", "g8f6", "e2e3", "f8g7", "f1c4", "d7d5", "c4d3", "e8g8", "c2c4",
             "h8f8", "g1f3", "b8c6", "c4d5", "c6b4", "d3c4", "b4d3", "c4d3",
         ];
 
+        // Read against the position e8g8 is the castle, which already took the rook to f8
+        let mut game = Game::new();
+        let error = game.play_line(&moves_list.join(" ")).expect_err("");
+        assert!(error.to_string().contains("h8f8"));
+        assert!(game.to_fen() == "rnbq1rk1/ppp1ppbp/5np1/3p4/2P5/1P1BP3/PB1P1PPP/RN1QK1NR b KQ c3 0 6");
 
+        let moves = game.get_moves();
+        assert!(moves.contains(&ChessMove::from_str("d5c4").expect("")));
+        assert!(moves.contains(&ChessMove::from_str("f8e8").expect("")));
     }
 
     #[test]
@@ -0,0 +1,129 @@
+use crate::game::{chess_move::ChessMove, Game};
+use eyre::{eyre, Result, WrapErr};
+use rand::Rng;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Weighted moves to play from known positions instead of searching.
+///
+/// Each line of the text format is `fen;move:weight,move:weight`, with moves in SAN or
+/// coordinate notation. Blank lines and lines starting with `#` are skipped
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct OpeningBook {
+    // Moves and their weights by the first four FEN fields, so the move counters don't matter
+    entries: HashMap<String, Vec<(ChessMove, u32)>>,
+}
+
+impl OpeningBook {
+    pub fn from_str(book: &str) -> Result<OpeningBook> {
+        let mut entries: HashMap<String, Vec<(ChessMove, u32)>> = HashMap::new();
+
+        for (line_number, line) in book.lines().enumerate().map(|(index, line)| (index + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (fen, moves) = line.split_once(';').ok_or_else(|| eyre!("Book line {} has no ';' after the FEN", line_number))?;
+            let game = Game::from_fen(fen.trim()).wrap_err_with(|| format!("Book line {} has an invalid FEN", line_number))?;
+            let entry = entries.entry(OpeningBook::key(&game)).or_default();
+
+            for weighted_move in moves.split(',').map(str::trim).filter(|weighted_move| !weighted_move.is_empty()) {
+                let (move_str, weight) = weighted_move.split_once(':').ok_or_else(|| eyre!("Book line {} move {} has no weight", line_number, weighted_move))?;
+                let weight: u32 = weight.trim().parse().wrap_err_with(|| format!("Book line {} move {} has an invalid weight", line_number, move_str))?;
+                let chess_move = game.clone().play_line(move_str.trim()).wrap_err_with(|| format!("Book line {}", line_number))?;
+
+                if let [chess_move] = chess_move[..] {
+                    entry.push((chess_move, weight));
+                }
+                else {
+                    return Err(eyre!("Book line {} move {} should be a single move", line_number, move_str));
+                }
+            }
+        }
+
+        Ok(OpeningBook {entries})
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<OpeningBook> {
+        let book = fs::read_to_string(path.as_ref()).wrap_err_with(|| format!("Could not read opening book {}", path.as_ref().display()))?;
+        OpeningBook::from_str(&book)
+    }
+
+    /// Gets the book moves and their weights for a position, if it is in the book
+    pub fn moves(&self, game: &Game) -> Option<&[(ChessMove, u32)]> {
+        self.entries.get(&OpeningBook::key(game)).map(Vec::as_slice)
+    }
+
+    /// Picks one of a position's book moves with probability proportional to its weight,
+    /// or None when the position isn't in the book or all its weights are 0
+    pub fn pick<R: Rng>(&self, game: &Game, rng: &mut R) -> Option<ChessMove> {
+        let moves = self.moves(game)?;
+        let total: u32 = moves.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut roll = rng.gen_range(0..total);
+        for (chess_move, weight) in moves {
+            if roll < *weight {
+                return Some(*chess_move);
+            }
+            roll -= weight;
+        }
+
+        None
+    }
+
+    fn key(game: &Game) -> String {
+        game.to_fen().split_whitespace().take(4).collect::<Vec<&str>>().join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const BOOK: &str = "# Open games
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1; e2e4:3, d4:1, c2c4:0
+rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1; c7c5:1,e5:1
+";
+
+    #[test]
+    fn test_from_str() {
+        let book = OpeningBook::from_str(BOOK).expect("");
+
+        let moves = book.moves(&Game::new()).expect("");
+        assert!(moves.len() == 3);
+        assert!(moves[1] == (ChessMove::from_str("d2d4").expect(""), 1));
+
+        // The move counters aren't part of the key
+        let game = Game::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 4 9").expect("");
+        assert!(book.moves(&game).is_some_and(|moves| moves.len() == 2));
+        assert!(book.moves(&game.peek_move(&ChessMove::from_str("e7e5").expect(""))).is_none());
+    }
+
+    #[test]
+    fn test_from_str_invalid() {
+        assert!(OpeningBook::from_str("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_err());
+        assert!(OpeningBook::from_str("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1; e2e4").is_err());
+        assert!(OpeningBook::from_str("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1; e2e5:1").is_err_and(|error| format!("{:#}", error).contains("e2e5")));
+        assert!(OpeningBook::from_str("\n# Nothing yet\n").is_ok_and(|book| book.moves(&Game::new()).is_none()));
+    }
+
+    #[test]
+    fn test_pick() {
+        let book = OpeningBook::from_str(BOOK).expect("");
+        let mut rng = StdRng::seed_from_u64(1523);
+
+        let picks: Vec<ChessMove> = (0..200).filter_map(|_| book.pick(&Game::new(), &mut rng)).collect();
+        let e4_picks = picks.iter().filter(|chess_move| chess_move.to_string() == "e2e4").count();
+        assert!(picks.len() == 200);
+        assert!(picks.iter().all(|chess_move| ["e2e4", "d2d4"].contains(&chess_move.to_string().as_str())));
+        assert!(e4_picks > 100 && e4_picks < 200);
+
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").expect("");
+        assert!(book.pick(&game, &mut rng).is_none());
+    }
+}
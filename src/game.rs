@@ -1,8 +1,14 @@
 pub mod board;
+pub mod bitboard;
 pub mod piece;
 pub mod chess_move;
 pub mod position;
+pub mod pgn;
 
+#[cfg(feature = "serde")]
+mod serialize;
+
+use std::fmt;
 use std::hash::Hash;
 
 use board::*;
@@ -10,8 +16,11 @@ use piece::*;
 use position::Position;
 use chess_move::ChessMove;
 use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use log::warn;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct CastleRights {
     pub kingside: bool,
     pub queenside: bool,
@@ -23,7 +32,112 @@ impl CastleRights {
     }
 }
 
-// TODO: Implement 50 moves rule
+struct ZobristKeys {
+    pieces: [[[u64; 64]; 6]; 2],
+    black_to_move: u64,
+    castle_rights: [[u64; 2]; 2],
+    en_passant: [u64; 8],
+}
+
+impl ZobristKeys {
+    fn new() -> ZobristKeys {
+        // Seeded so that keys are stable between runs
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let mut keys = ZobristKeys {
+            pieces: [[[0; 64]; 6]; 2],
+            black_to_move: rng.gen(),
+            castle_rights: [[0; 2]; 2],
+            en_passant: [0; 8],
+        };
+
+        for square in keys.pieces.iter_mut().flatten().flatten() {
+            *square = rng.gen();
+        }
+
+        for right in keys.castle_rights.iter_mut().flatten() {
+            *right = rng.gen();
+        }
+
+        for file in keys.en_passant.iter_mut() {
+            *file = rng.gen();
+        }
+
+        keys
+    }
+}
+
+/// Forsyth-Edwards Notation for the standard starting position
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+lazy_static! {
+    static ref ZOBRIST_KEYS: ZobristKeys = ZobristKeys::new();
+    // Parsed once so new games don't decode the start FEN every time
+    static ref START_POSITION: Game = Game::from_fen(START_FEN).expect("Decode standard FEN failed");
+}
+
+fn piece_key(piece: &Piece, position: &Position) -> u64 {
+    ZOBRIST_KEYS.pieces[piece.color as usize][piece.piece_type as usize - 1][position.index()]
+}
+
+// Rough piece values for judging whether a capture wins or loses material
+fn exchange_value(piece_type: PieceType) -> u8 {
+    match piece_type {
+        PieceType::Pawn => 1,
+        PieceType::Knight | PieceType::Bishup => 3,
+        PieceType::Rook => 5,
+        PieceType::Queen => 9,
+        PieceType::King => u8::MAX,
+    }
+}
+
+/// The state of a game from the perspective of the side to move.
+///
+/// Claimable draws (fifty moves, threefold repetition) only end the game if a
+/// player claims them, whereas the seventy-five move and fivefold repetition
+/// limits end the game automatically.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    Ongoing,
+    /// Contains the color of the side that has been checkmated
+    Checkmate(PieceColor),
+    Stalemate,
+    FiftyMoveClaimable,
+    ThreefoldClaimable,
+    SeventyFiveMove,
+    Fivefold,
+    /// Neither side has the pieces left to ever give checkmate
+    InsufficientMaterial,
+}
+
+/// What happened when a move was made
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MoveInfo {
+    /// The piece removed from the board, including a pawn taken en passant
+    pub captured: Option<Piece>,
+    pub is_en_passant: bool,
+    pub promotion: Option<PieceType>,
+    pub is_castle: bool,
+}
+
+impl MoveInfo {
+    pub fn is_capture(&self) -> bool {
+        self.captured.is_some()
+    }
+}
+
+/// Everything unmake_move needs to take back a move made with make_move_undoable
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MoveUndo {
+    pub chess_move: ChessMove,
+    pub info: MoveInfo,
+    castle_rights: [CastleRights; 2],
+    en_passant: Option<Position>,
+    full_moves: u16,
+    halfmove_clock: u16,
+    key: u64,
+    // The repetition history an irreversible move cleared, otherwise unmaking only drops the last key
+    history: Option<Vec<u64>>,
+}
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Game {
@@ -31,7 +145,13 @@ pub struct Game {
     pub en_passant: Option<Position>,
     pub turn: PieceColor,
     pub castle_rights: [CastleRights; 2],
-    pub half_moves: u16,
+    // Fullmove number, starting at 1 and incremented after each of black's moves
+    pub full_moves: u16,
+    pub halfmove_clock: u16,
+    // Zobrist key of the current position, kept up to date by make_move
+    key: u64,
+    // Keys of every position since the last capture or pawn move, including the current one
+    history: Vec<u64>,
 }
 
 impl Game {
@@ -41,18 +161,27 @@ impl Game {
             en_passant: None,
             turn: PieceColor::White,
             castle_rights: [CastleRights::default(); 2],
-            half_moves: 0,
+            full_moves: 1,
+            halfmove_clock: 0,
+            key: 0,
+            history: vec!(),
         }
     }
 
     pub fn new() -> Game {
-        Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").expect("Decode standard FEN failed")
+        Game::startpos()
+    }
+
+    /// The standard starting position
+    pub fn startpos() -> Game {
+        START_POSITION.clone()
     }
 
     pub fn from_fen(fen_str: &str) -> Result<Game> {
         let mut result = Game::default();
 
-        let sections: Vec<&str> = fen_str.split(' ').collect();
+        // Anything after the six fields, such as a move list some tools append, is ignored
+        let sections: Vec<&str> = fen_str.split_whitespace().take(6).collect();
 
         if sections.len() != 6 {
             return Err(eyre!("Too few segments"));
@@ -119,13 +248,13 @@ impl Game {
                 Ok(pos) => {
                     let pos_tuple = pos.decode();
                     match result.turn {
-                        PieceColor::Black => if pos_tuple.0 == 2 && result.board.get(&pos.forward(&!result.turn)).map_or(false, |&p| p == Piece { piece_type: PieceType::Pawn, color: PieceColor::White }) {
+                        PieceColor::Black => if pos_tuple.0 == 2 && result.board.get(&pos.forward(&!result.turn)).is_some_and(|&p| p == Piece { piece_type: PieceType::Pawn, color: PieceColor::White }) {
                             Some(pos)
                         }
                         else {
                             return Err(eyre!("Invalid En Passant Black"))
                         },
-                        PieceColor::White => if pos_tuple.0 == 5 && result.board.get(&pos.forward(&!result.turn)).map_or(false, |&p| p == Piece { piece_type: PieceType::Pawn, color: PieceColor::Black }) {
+                        PieceColor::White => if pos_tuple.0 == 5 && result.board.get(&pos.forward(&!result.turn)).is_some_and(|&p| p == Piece { piece_type: PieceType::Pawn, color: PieceColor::Black }) {
                             Some(pos)
                         }
                         else {
@@ -137,9 +266,116 @@ impl Game {
             };
         }
 
+        result.halfmove_clock = match sections[4].parse::<u16>() {
+            Ok(clock) => clock,
+            Err(msg) => return Err(eyre!("Invalid halfmove clock {}, {}", sections[4], msg)),
+        };
+
+        // Some tools write 0 for the fullmove number, which is treated as the first move
+        result.full_moves = match sections[5].parse::<u16>() {
+            Ok(full_moves) => full_moves.max(1),
+            Err(msg) => return Err(eyre!("Invalid fullmove number {}, {}", sections[5], msg)),
+        };
+
+        result.key = result.position_key();
+        result.history.push(result.key);
+
+        Ok(result)
+    }
+
+    /// Packs the position into 38 bytes: the board as one nibble per square from a1 to h8,
+    /// a flags byte for the side to move and castle rights, the en passant square (0xFF for
+    /// none) and the halfmove clock and fullmove number as little endian u16s.
+    ///
+    /// Like a FEN string this leaves out the repetition history.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; 32];
+
+        for row in 0usize..=7usize {
+            for column in 0usize..=7usize {
+                let index = row * 8 + column;
+                let nibble = self.board.get(&Position::encode(row, column)).map_or(0, |piece| {
+                    piece.piece_type as u8 | (piece.color as u8) << 3
+                });
+                bytes[index / 2] |= nibble << (4 * (index % 2));
+            }
+        }
+
+        let flags = (self.turn as u8)
+            | (self.castle_rights[PieceColor::White as usize].kingside as u8) << 1
+            | (self.castle_rights[PieceColor::White as usize].queenside as u8) << 2
+            | (self.castle_rights[PieceColor::Black as usize].kingside as u8) << 3
+            | (self.castle_rights[PieceColor::Black as usize].queenside as u8) << 4;
+        bytes.push(flags);
+        bytes.push(self.en_passant.map_or(0xFF, |position| position.index() as u8));
+        bytes.extend_from_slice(&self.halfmove_clock.to_le_bytes());
+        bytes.extend_from_slice(&self.full_moves.to_le_bytes());
+
+        bytes
+    }
+
+    /// Decodes a position written by to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Game> {
+        if bytes.len() != 38 {
+            return Err(eyre!("Expected 38 bytes but got {}", bytes.len()));
+        }
+
+        let mut result = Game::default();
+
+        for index in 0usize..64usize {
+            let nibble = (bytes[index / 2] >> (4 * (index % 2))) & 0b1111;
+            if nibble == 0 {
+                continue;
+            }
+
+            let color = if nibble & 0b1000 == 0 { PieceColor::Black } else { PieceColor::White };
+            let piece_type = match nibble & 0b111 {
+                1 => PieceType::Pawn,
+                2 => PieceType::Knight,
+                3 => PieceType::Bishup,
+                4 => PieceType::Rook,
+                5 => PieceType::Queen,
+                6 => PieceType::King,
+                _ => return Err(eyre!("Invalid piece nibble {} at square {}", nibble, index)),
+            };
+            result.board.add_piece(Piece{piece_type, color}, &Position::encode(index / 8, index % 8));
+        }
+
+        let flags = bytes[32];
+        if flags >> 5 != 0 {
+            return Err(eyre!("Invalid flags byte {}", flags));
+        }
+
+        result.turn = if flags & 1 == 0 { PieceColor::Black } else { PieceColor::White };
+        result.castle_rights[PieceColor::White as usize] = CastleRights{kingside: flags & 0b10 != 0, queenside: flags & 0b100 != 0};
+        result.castle_rights[PieceColor::Black as usize] = CastleRights{kingside: flags & 0b1000 != 0, queenside: flags & 0b10000 != 0};
+
+        result.en_passant = match bytes[33] {
+            0xFF => None,
+            square @ 0..=63 => Position::from_index(square as usize),
+            square => return Err(eyre!("Invalid en passant square {}", square)),
+        };
+
+        result.halfmove_clock = u16::from_le_bytes([bytes[34], bytes[35]]);
+        result.full_moves = u16::from_le_bytes([bytes[36], bytes[37]]).max(1);
+
+        result.key = result.position_key();
+        result.history.push(result.key);
+
         Ok(result)
     }
 
+    /// Total half moves played since the start of the game, including any before the FEN
+    /// this game was loaded from
+    pub fn ply(&self) -> u32 {
+        (self.full_moves as u32 - 1) * 2 + (self.turn == PieceColor::Black) as u32
+    }
+
+    /// The 1-based fullmove number, which goes up once black has moved
+    pub fn move_number(&self) -> u32 {
+        self.full_moves as u32
+    }
+
     pub fn to_fen(&self) -> String {
         let mut board = "".to_owned();
 
@@ -191,14 +427,220 @@ impl Game {
             castle = "-".to_owned();
         }
 
-        format!("{} {} {} {}", board, self.turn, castle, self.en_passant.map_or("-".to_owned(), |position| position.to_string()))
+        format!("{} {} {} {} {} {}", board, self.turn, castle, self.en_passant.map_or("-".to_owned(), |position| position.to_string()), self.halfmove_clock, self.full_moves)
+    }
+
+    /// Parses a move in coordinate notation using the position to fill in what the text
+    /// leaves out: a pawn reaching the last rank without a piece letter promotes to a queen
+    /// and a king moving two files, as UCI writes castling, becomes the castle move
+    pub fn parse_move(&self, move_str: &str) -> Option<ChessMove> {
+        let chess_move = ChessMove::from_str(move_str)?;
+
+        if let ChessMove::Move(from, to) = chess_move {
+            match self.board.get(&from) {
+                Some(&Piece{piece_type: PieceType::Pawn, color}) if to.relative_rank(&color) == 7 => {
+                    return Some(ChessMove::PawnPromote(from, to, PieceType::Queen));
+                }
+                Some(&Piece{piece_type: PieceType::King, color: _}) if from.row() == to.row() && from.column() == 4 => {
+                    match to.column() {
+                        6 => return Some(ChessMove::CastleKingside),
+                        2 => return Some(ChessMove::CastleQueenside),
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Some(chess_move)
+    }
+
+    /// Finds the legal move written in standard algebraic notation, ignoring any check,
+    /// mate or annotation suffix
+    pub fn parse_san(&self, san: &str) -> Option<ChessMove> {
+        let strip = |text: &str| text.trim_end_matches(['+', '#', '!', '?']).to_string();
+        let san = strip(san).replace("0-0-0", "O-O-O").replace("0-0", "O-O");
+
+        self.get_moves().into_iter().find(|chess_move| strip(&chess_move.to_san(self)) == san)
+    }
+
+    /// Plays a line of moves given in SAN or coordinate notation, such as "1. e4 e5 2. Nf3"
+    /// or "e2e4 e7e5 g1f3", skipping move numbers and returning the moves made
+    pub fn play_line(&mut self, line: &str) -> Result<Vec<ChessMove>> {
+        let mut moves = vec!();
+
+        for token in line.split_whitespace() {
+            // Move numbers may be written apart ("1. e4") or joined to the move ("1.e4"). Only
+            // digits followed by dots are a move number, so "0-0" keeps its zeros
+            let after_digits = token.trim_start_matches(|c: char| c.is_ascii_digit());
+            let token = if after_digits.len() < token.len() && after_digits.starts_with('.') { after_digits.trim_start_matches('.') } else { token };
+            if token.is_empty() {
+                continue;
+            }
+
+            let legal_moves = self.get_moves();
+            let chess_move = self.parse_move(token).filter(|chess_move| legal_moves.contains(chess_move))
+                .or_else(|| self.parse_san(token))
+                .ok_or_else(|| eyre!("Move {} is not legal in {}", token, self.to_fen()))?;
+
+            self.make_move(&chess_move);
+            moves.push(chess_move);
+        }
+
+        Ok(moves)
+    }
+
+    /// Plays random legal moves from the start position until the game is over or
+    /// `max_plies` moves have been made, for fuzzing move generation
+    pub fn random_legal_game<R: Rng>(rng: &mut R, max_plies: usize) -> Vec<ChessMove> {
+        let mut game = Game::new();
+        let mut moves = vec!();
+
+        while moves.len() < max_plies && matches!(game.status(), GameResult::Ongoing | GameResult::FiftyMoveClaimable | GameResult::ThreefoldClaimable) {
+            let legal_moves = game.get_moves();
+            let chess_move = legal_moves[rng.gen_range(0..legal_moves.len())];
+            game.make_move(&chess_move);
+            moves.push(chess_move);
+        }
+
+        moves
     }
 
     /// Gets all valid moves from a specific chess position
     pub fn get_moves(&self) -> Vec<ChessMove> {
-        let mut moves = vec!();
+        self.generate_moves(None, true, false)
+    }
+
+    /// Gets all moves which follow the pieces' movement rules without checking whether
+    /// they leave the mover's king in check. Castling is still only generated when legal.
+    pub fn pseudo_legal_moves(&self) -> Vec<ChessMove> {
+        self.generate_moves(None, false, false)
+    }
+
+    /// Whether the mover's king is safe after making a pseudo-legal move
+    pub fn is_legal_after(&self, chess_move: &ChessMove) -> bool {
+        let mut next_game = self.clone();
+        next_game.apply_move(chess_move);
+        next_game.board.get_king(&self.turn).is_some_and(|king| !next_game.board.has_check(&king, &self.turn))
+    }
+
+    /// Gets all valid moves made by the side's pieces of one type, including castling for the king
+    pub fn moves_for_piece_type(&self, piece_type: PieceType) -> Vec<ChessMove> {
+        self.generate_moves(Some(piece_type), true, false)
+    }
+
+    /// Whether the side to move has at least one legal move, stopping as soon as one is found
+    pub fn has_any_legal_move(&self) -> bool {
+        !self.generate_moves(None, true, true).is_empty()
+    }
+
+    /// Whether the side to move is in check and has no legal moves
+    pub fn is_checkmate(&self) -> bool {
+        self.is_in_check() && !self.has_any_legal_move()
+    }
+
+    /// Whether the side to move is not in check but has no legal moves
+    pub fn is_stalemate(&self) -> bool {
+        !self.is_in_check() && !self.has_any_legal_move()
+    }
+
+    /// Whether the position can be judged without searching further: the side to move isn't
+    /// in check and has no capture or promotion that a shallow exchange estimate says doesn't lose
+    pub fn is_quiet(&self) -> bool {
+        !self.is_in_check() && self.loud_moves().is_empty()
+    }
+
+    /// Gets the captures and promotions which is_quiet looks for, best mvv_lva first
+    pub fn loud_moves(&self) -> Vec<ChessMove> {
+        let mut moves: Vec<ChessMove> = self.get_moves().into_iter().filter(|chess_move| self.is_loud(chess_move)).collect();
+        moves.sort_by_key(|chess_move| std::cmp::Reverse(self.mvv_lva(chess_move)));
+        moves
+    }
+
+    /// Scores a move for search ordering by Most Valuable Victim / Least Valuable Attacker,
+    /// so captures of big pieces by small ones come first and promotions are boosted by
+    /// the piece promoted to. Moves that neither capture nor promote score 0
+    pub fn mvv_lva(&self, chess_move: &ChessMove) -> i32 {
+        let (from, to) = match chess_move {
+            ChessMove::CastleKingside | ChessMove::CastleQueenside => return 0,
+            ChessMove::Move(from, to) | ChessMove::PawnPromote(from, to, _) => (from, to),
+        };
+
+        let attacker = match self.board.get(from) {
+            Some(piece) => piece.piece_type,
+            None => return 0,
+        };
+
+        let victim = match self.board.get(to) {
+            Some(piece) => Some(piece.piece_type),
+            None if attacker == PieceType::Pawn && self.en_passant == Some(*to) => Some(PieceType::Pawn),
+            None => None,
+        };
+
+        // The king never gets captured, and as an attacker it only needs to rank below the queen
+        let mut score = victim.map_or(0, |victim| 16 * exchange_value(victim) as i32 - std::cmp::min(exchange_value(attacker), 10) as i32);
+        if let ChessMove::PawnPromote(_, _, promoted) = chess_move {
+            score += 16 * exchange_value(*promoted) as i32;
+        }
+
+        score
+    }
+
+    // A capture or promotion that either takes at least as much as the moving piece is worth
+    // or lands on a square the opponent doesn't defend
+    fn is_loud(&self, chess_move: &ChessMove) -> bool {
+        let (from, to) = match chess_move {
+            ChessMove::CastleKingside | ChessMove::CastleQueenside => return false,
+            ChessMove::Move(from, to) | ChessMove::PawnPromote(from, to, _) => (from, to),
+        };
+
+        let mover = match self.board.get(from) {
+            Some(piece) => piece.piece_type,
+            None => return false,
+        };
+
+        let captured = match self.board.get(to) {
+            Some(piece) => Some(piece.piece_type),
+            None if mover == PieceType::Pawn && self.en_passant == Some(*to) => Some(PieceType::Pawn),
+            None => None,
+        };
 
-        // TODO: Optimize function so we don't have to look at every check
+        if captured.is_none() && !matches!(chess_move, ChessMove::PawnPromote(_, _, _)) {
+            return false;
+        }
+
+        let is_defended = !self.board.get_attackers(to, &self.turn).is_empty();
+        !is_defended || captured.is_some_and(|captured| exchange_value(captured) >= exchange_value(mover))
+    }
+
+    /// Whether a legal move puts the opponent in check, including discovered checks.
+    /// Whether it's also mate can be asked of the position peek_move returns
+    pub fn gives_check(&self, chess_move: &ChessMove) -> bool {
+        self.peek_move(chess_move).is_in_check()
+    }
+
+    /// Gets the positions of the pieces giving check to the side to move
+    pub fn checkers(&self) -> Vec<Position> {
+        match self.board.get_king(&self.turn) {
+            Some(king) => self.board.get_attackers(&king, &self.turn),
+            None => vec!(),
+        }
+    }
+
+    /// Whether the side to move is in check
+    pub fn is_in_check(&self) -> bool {
+        self.board.get_king(&self.turn).is_some_and(|king| self.board.has_check(&king, &self.turn))
+    }
+
+    /// Generates moves for the side to move
+    ///
+    /// # Arguments
+    ///
+    /// * `piece_filter` - Only generate moves for pieces of this type
+    /// * `legal_only` - Skip moves which leave the mover's king in check
+    /// * `stop_at_first` - Return once any piece has produced a move
+    fn generate_moves(&self, piece_filter: Option<PieceType>, legal_only: bool, stop_at_first: bool) -> Vec<ChessMove> {
+        let mut moves = vec!();
 
         // Go through all pieces and check for valid moves
         let piece_positions: Vec<(Position, PieceType)> = self.board.get_pieces(&self.turn);
@@ -206,54 +648,85 @@ impl Game {
         let (king_position, _) = match piece_positions.iter().find(|(_, piece_type)| *piece_type == PieceType::King) {
             Some(val) => val,
             None => {
-                println!("Attempted to get moves but piece list has no king!");
+                warn!("Attempted to get moves but piece list has no king!");
                 return moves;
             }
         };
 
-        for (from, cur_piece_type) in piece_positions.iter() {
+        // When in check, pieces other than the king may only capture a lone checker or block its line.
+        // In double check no square works, so only the king can move.
+        let evasion_squares: Option<Vec<Position>> = if legal_only {
+            match self.board.get_attackers(king_position, &self.turn).as_slice() {
+                [] => None,
+                [checker] => {
+                    let mut squares = king_position.squares_between(checker);
+                    squares.push(*checker);
+                    Some(squares)
+                },
+                _ => Some(vec!()),
+            }
+        }
+        else {
+            None
+        };
+
+        let evades_check = |from: &Position, to: &Position| from == king_position || evasion_squares.as_ref().is_none_or(|squares| squares.contains(to));
+
+        // Pieces other than the king only need to stay on their pin ray and answer any check,
+        // so only king moves have to be tried on a copy of the board
+        let pins = if legal_only { self.board.pinned_pieces(king_position, &self.turn) } else { vec!() };
+        let is_safe = |from: &Position, to: &Position, test_king_position: &Position| {
+            if !legal_only {
+                true
+            }
+            else if from == king_position {
+                self.board.test_move(from, to, test_king_position, &self.turn)
+            }
+            else {
+                let (row_offset, column_offset) = (to.row() as isize - king_position.row() as isize, to.column() as isize - king_position.column() as isize);
+                evades_check(from, to) && pins.iter().find(|(pinned, _)| pinned == from).is_none_or(|(_, (row_step, column_step))| row_offset * column_step == column_offset * row_step)
+            }
+        };
+
+        for (from, cur_piece_type) in piece_positions.iter().filter(|(_, piece_type)| piece_filter.is_none_or(|filter| filter == *piece_type)) {
             match cur_piece_type {
                 PieceType::King => {
-                    let (king_row, king_column) = from.decode_isize();
-
-                    for increments in [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)] {
-                        if let Some(to) = Position::encode_checked(king_row + increments.0, king_column + increments.1) {
-                            if self.board.get(&to).map_or(true, |&Piece{piece_type: _, color}| color != self.turn) && self.board.test_move(from, &to, &to, &self.turn) {
-                                moves.push(ChessMove::Move(*from, to));
-                            }
+                    for to in from.neighbors() {
+                        if self.board.get(&to).is_none_or(|&Piece{piece_type: _, color}| color != self.turn) && is_safe(from, &to, &to) {
+                            moves.push(ChessMove::Move(*from, to));
                         }
                     }
                 },
                 PieceType::Queen => {
                     for to in self.board.get_bishup_move_positions(from, &self.turn, false) {
-                        if self.board.test_move(from, &to, king_position, &self.turn) {
+                        if is_safe(from, &to, king_position) {
                             moves.push(ChessMove::Move(*from, to));
                         }
                     }
 
                     for to in self.board.get_rook_move_positions(from, &self.turn, false) {
-                        if self.board.test_move(from, &to, king_position, &self.turn) {
+                        if is_safe(from, &to, king_position) {
                             moves.push(ChessMove::Move(*from, to));
                         }
                     }
                 },
                 PieceType::Bishup => {
                     for to in self.board.get_bishup_move_positions(from, &self.turn, false) {
-                        if self.board.test_move(from, &to, king_position, &self.turn) {
+                        if is_safe(from, &to, king_position) {
                             moves.push(ChessMove::Move(*from, to));
                         }
                     }
                 },
                 PieceType::Rook => {
                     for to in self.board.get_rook_move_positions(from, &self.turn, false) {
-                        if self.board.test_move(from, &to, king_position, &self.turn) {
+                        if is_safe(from, &to, king_position) {
                             moves.push(ChessMove::Move(*from, to));
                         }
                     }
                 },
                 PieceType::Knight => {
                     for to in self.board.get_knight_move_positions(from, &self.turn, false) {
-                        if self.board.test_move(from, &to, king_position, &self.turn) {
+                        if is_safe(from, &to, king_position) {
                             moves.push(ChessMove::Move(*from, to));
                         }
                     }
@@ -263,9 +736,8 @@ impl Game {
                     let promotion_types = [PieceType::Queen, PieceType::Rook, PieceType::Bishup, PieceType::Knight];
 
                     let to = from.forward(&self.turn);
-                    let (to_row, to_column) = to.decode_isize();
                     if self.board.get(&to).is_none() {
-                        if self.board.test_move(from, &to, king_position, &self.turn) {
+                        if is_safe(from, &to, king_position) {
                             if must_promote {
                                 for piece_type in promotion_types {
                                     moves.push(ChessMove::PawnPromote(*from, to, piece_type))
@@ -278,7 +750,7 @@ impl Game {
 
                         if [(PieceColor::Black, 6usize), (PieceColor::White, 1usize)].contains(&(self.turn, from.row())) {
                             let to = to.forward(&self.turn);
-                            if self.board.get(&to).is_none() && self.board.test_move(from, &to, king_position, &self.turn) {
+                            if self.board.get(&to).is_none() && is_safe(from, &to, king_position) {
                                 if must_promote {
                                     for piece_type in promotion_types {
                                         moves.push(ChessMove::PawnPromote(*from, to, piece_type))
@@ -292,16 +764,15 @@ impl Game {
                     }
 
                     // Check captures
-                    for position_values in [(to_row, to_column + 1),(to_row, to_column - 1)] {
-                        if let Some(to) = Position::encode_checked(position_values.0, position_values.1) {
+                    for column_step in [1, -1] {
+                        if let Some(to) = to.offset(0, column_step) {
                             if Some(to) == self.en_passant {
-                                let mut next_board = self.board;
-                                next_board.make_move(from, &to);
-                                if !next_board.has_check(king_position, &self.turn) {
+                                let evades_en_passant_check = evades_check(from, &to) || evades_check(from, &to.backward(&self.turn));
+                                if evades_en_passant_check && (!legal_only || self.board.test_en_passant_move(from, &to, king_position, &self.turn)) {
                                     moves.push(ChessMove::Move(*from, to));
                                 }
                             }
-                            else if self.board.get(&to).map_or(false, |&Piece{piece_type: _, color}| color != self.turn) && self.board.test_move(from, &to, king_position, &self.turn) {
+                            else if self.board.get(&to).is_some_and(|&Piece{piece_type: _, color}| color != self.turn) && is_safe(from, &to, king_position) {
                                 if must_promote {
                                     for piece_type in promotion_types {
                                         moves.push(ChessMove::PawnPromote(*from, to, piece_type))
@@ -315,8 +786,24 @@ impl Game {
                     }
                 },
             }
+
+            if stop_at_first && !moves.is_empty() {
+                return moves;
+            }
         }
 
+        if piece_filter.is_some_and(|filter| filter != PieceType::King) {
+            return moves;
+        }
+
+        // One attack map answers the king and transit square checks for both castles
+        let enemy_attacks = if self.castle_rights[self.turn as usize].kingside || self.castle_rights[self.turn as usize].queenside {
+            self.board.attack_map(&!self.turn)
+        } else {
+            [[false; 8]; 8]
+        };
+        let is_attacked = |position: &Position| enemy_attacks[position.row()][position.column()];
+
         // Check for Castle Kingside
         if self.castle_rights[self.turn as usize].kingside {
             let transit_positions = match self.turn {
@@ -326,12 +813,12 @@ impl Game {
 
             // Make sure middle values are empty and king can't pass through check
             let mut is_kingside_valid = true;
-            if self.board.has_check(king_position, &self.turn) {
+            if is_attacked(king_position) {
                 is_kingside_valid = false;
             }
 
             for transit_position in transit_positions {
-                if self.board.get(&transit_position).is_some() || self.board.has_check(&transit_position, &self.turn) {
+                if self.board.get(&transit_position).is_some() || is_attacked(&transit_position) {
                     is_kingside_valid = false;
                     break;
                 }
@@ -355,12 +842,12 @@ impl Game {
                 is_queenside_valid = false;
             }
 
-            if self.board.has_check(king_position, &self.turn) {
+            if is_attacked(king_position) {
                 is_queenside_valid = false;
             }
 
             for transit_position in transit_positions {
-                if self.board.get(&transit_position).is_some() || self.board.has_check(&transit_position, &self.turn) {
+                if self.board.get(&transit_position).is_some() || is_attacked(&transit_position) {
                     is_queenside_valid = false;
                     break;
                 }
@@ -374,15 +861,101 @@ impl Game {
         moves
     }
 
+    /// Gets all legal moves sorted by from square, then to square, then promotion piece.
+    ///
+    /// Squares are ordered a1, b1, ..., h1, a2, ..., h8 and castling is ordered by the
+    /// king's from and to squares. Promotions are ordered knight, bishop, rook, queen.
+    pub fn ordered_moves(&self) -> Vec<ChessMove> {
+        let mut moves = self.get_moves();
+        moves.sort_by_key(|chess_move| self.move_order_key(chess_move));
+        moves
+    }
+
+    fn move_order_key(&self, chess_move: &ChessMove) -> (usize, usize, usize) {
+        let back_row = match self.turn {
+            PieceColor::White => 0,
+            PieceColor::Black => 7,
+        };
+
+        let (from, to, promotion) = match chess_move {
+            ChessMove::CastleKingside => (Position::encode(back_row, 4), Position::encode(back_row, 6), 0),
+            ChessMove::CastleQueenside => (Position::encode(back_row, 4), Position::encode(back_row, 2), 0),
+            ChessMove::Move(from, to) => (*from, *to, 0),
+            ChessMove::PawnPromote(from, to, piece_type) => (*from, *to, *piece_type as usize),
+        };
+
+        (from.index(), to.index(), promotion)
+    }
+
+    /// Gets all legal moves which capture a piece, including en passant
+    pub fn captures(&self) -> Vec<ChessMove> {
+        self.get_moves().into_iter().filter(|chess_move| chess_move.is_capture(&self.board)).collect()
+    }
+
+    /// Gets all legal moves which put the opponent's king in check
+    pub fn checks(&self) -> Vec<ChessMove> {
+        self.get_moves().into_iter().filter(|chess_move| {
+            let next_game = self.peek_move(chess_move);
+            next_game.board.get_king(&next_game.turn).is_some_and(|king| next_game.board.has_check(&king, &next_game.turn))
+        }).collect()
+    }
+
     /// Performs a move on a board in place without validation
+    ///
+    /// Debug builds check the resulting position with validate and panic if the move was illegal.
     /// 
     /// # Arguments
     /// 
     /// * `chess_move` - A ChessMove generated by the get_moves function 
     pub fn make_move(&mut self, chess_move: &ChessMove) {
+        self.apply_move(chess_move);
+
+        if cfg!(debug_assertions) {
+            if let Err(error) = self.validate() {
+                panic!("Move {} produced an illegal position {}: {}", chess_move, self.to_fen(), error);
+            }
+            assert!(self.key == self.position_key(), "Move {} left a stale Zobrist key in {}", chess_move, self.to_fen());
+        }
+    }
+
+    /// Checks that both kings are on the board and the side that just moved isn't left in check
+    pub fn validate(&self) -> Result<()> {
+        let white_king = self.board.get_king(&PieceColor::White).ok_or_else(|| eyre!("White has no king"))?;
+        let black_king = self.board.get_king(&PieceColor::Black).ok_or_else(|| eyre!("Black has no king"))?;
+
+        let (mover, mover_king) = match self.turn {
+            PieceColor::White => (PieceColor::Black, black_king),
+            PieceColor::Black => (PieceColor::White, white_king),
+        };
+
+        if self.board.has_check(&mover_king, &mover) {
+            return Err(eyre!("{:?} moved into check", mover));
+        }
+
+        Ok(())
+    }
+
+    fn apply_move(&mut self, chess_move: &ChessMove) {
         let mut remove_en_passant = true;
 
-        self.half_moves += 1;
+        if self.turn == PieceColor::Black {
+            self.full_moves = self.full_moves.saturating_add(1);
+        }
+        self.halfmove_clock += 1;
+
+        // Captures and pawn moves can never be undone so earlier positions can't repeat
+        let is_irreversible = match chess_move {
+            ChessMove::CastleKingside | ChessMove::CastleQueenside => false,
+            ChessMove::Move(from, to) => self.board.get(to).is_some() || self.board.get(from).is_some_and(|&Piece{piece_type, color: _}| piece_type == PieceType::Pawn),
+            ChessMove::PawnPromote(_, _, _) => true,
+        };
+
+        if is_irreversible {
+            self.halfmove_clock = 0;
+            self.history.clear();
+        }
+
+        self.key ^= self.state_key();
 
         match chess_move {
             ChessMove::CastleKingside => {
@@ -394,8 +967,8 @@ impl Game {
                     PieceColor::Black => (Position::encode(7, 4), Position::encode(7, 6), Position::encode(7, 7), Position::encode(7, 5)),
                 };
 
-                self.board.make_move(&king_from, &king_to);
-                self.board.make_move(&rook_from, &rook_to);
+                self.move_piece(&king_from, &king_to);
+                self.move_piece(&rook_from, &rook_to);
             },
             ChessMove::CastleQueenside => {
                 self.castle_rights[self.turn as usize].kingside = false;
@@ -406,37 +979,23 @@ impl Game {
                     PieceColor::Black => (Position::encode(7, 4), Position::encode(7, 2), Position::encode(7, 0), Position::encode(7, 3)),
                 };
 
-                self.board.make_move(&king_from, &king_to);
-                self.board.make_move(&rook_from, &rook_to);
+                self.move_piece(&king_from, &king_to);
+                self.move_piece(&rook_from, &rook_to);
             },
             ChessMove::Move(from, to) => {
                 // Handle moves which would break castling rights.
-                if self.board.get(from).map_or(false, |&Piece{piece_type, color: _}| piece_type == PieceType::King) {
+                if self.board.get(from).is_some_and(|&Piece{piece_type, color: _}| piece_type == PieceType::King) {
                     self.castle_rights[self.turn as usize].kingside = false;
                     self.castle_rights[self.turn as usize].queenside = false;
                 }
-                else if self.board.get(from).map_or(false, |&Piece{piece_type, color: _}| piece_type == PieceType::Rook) {
-                    if from.column() == 7 {
-                        self.castle_rights[self.turn as usize].kingside = false;
-                    }
-                    else {
-                        self.castle_rights[self.turn as usize].queenside = false;
-                    }
-                }
 
-                // Handle rook captures
-                if self.board.get(to).map_or(false, |&Piece{piece_type, color: _}| piece_type == PieceType::Rook) {
-                    if to.column() == 7 {
-                        self.castle_rights[!self.turn as usize].kingside = false;
-                    }
-                    else {
-                        self.castle_rights[!self.turn as usize].queenside = false;
-                    }
-                }
+                // Handle rooks leaving or being captured on their starting corners
+                self.clear_corner_castle_rights(from);
+                self.clear_corner_castle_rights(to);
 
                 // Handle capture by en passants
-                if Some(to) == self.en_passant.as_ref() && self.board.get(from).map_or(false, |Piece{piece_type, color: _}| piece_type == &PieceType::Pawn) {
-                    self.board.remove_piece(&to.backward(&self.turn)).take();
+                if Some(to) == self.en_passant.as_ref() && self.board.get(from).is_some_and(|Piece{piece_type, color: _}| piece_type == &PieceType::Pawn) {
+                    self.remove_piece(&to.backward(&self.turn));
                 }
 
                 // Handle double move and marking en passant square
@@ -448,27 +1007,20 @@ impl Game {
                 let from_row = from.row();
                 let to_row = to.row();
 
-                if (from_row, to_row) == double_move_from_to && self.board.get(from).map_or(false, |&Piece{piece_type, color: _}| piece_type == PieceType::Pawn) {
+                if (from_row, to_row) == double_move_from_to && self.board.get(from).is_some_and(|&Piece{piece_type, color: _}| piece_type == PieceType::Pawn) {
                     self.en_passant = Some(to.clone().backward(&self.turn));
                     remove_en_passant = false;
                 }
 
-                self.board.make_move(from, to);
+                self.move_piece(from, to);
 
             },
             ChessMove::PawnPromote(from, to, piece_type) => {
                 // Handle rook captures
-                if self.board.get(to).map_or(false, |&Piece{piece_type, color: _}| piece_type == PieceType::Rook) {
-                    if to.column() == 7 {
-                        self.castle_rights[!self.turn as usize].kingside = false;
-                    }
-                    else {
-                        self.castle_rights[!self.turn as usize].queenside = false;
-                    }
-                }
+                self.clear_corner_castle_rights(to);
 
-                self.board.remove_piece(from);
-                self.board.add_piece(Piece{piece_type: *piece_type, color: self.turn}, to);
+                self.remove_piece(from);
+                self.add_piece(Piece{piece_type: *piece_type, color: self.turn}, to);
             },
         }
 
@@ -476,104 +1028,1204 @@ impl Game {
         if remove_en_passant {
             self.en_passant = None;
         }
-    }
-
-    #[allow(dead_code)]
-    pub fn print(&self) {
-        println!("{}'s Turn", self.turn);
-        println!("k:{}, q:{}, K:{}, Q:{}", self.castle_rights[0].kingside, self.castle_rights[0].queenside,self.castle_rights[1].kingside, self.castle_rights[1].queenside);
 
-        self.board.print();
+        self.key ^= self.state_key();
+        self.history.push(self.key);
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    impl Game {
-        pub fn perft(&mut self, depth: usize) -> Vec<(ChessMove, usize)> {
-            let moves = self.get_moves();
-        
-            let mut result: Vec<(ChessMove, usize)> = vec!();
-        
-            for chess_move in moves.iter() {
-                let mut next_game = self.clone();
-                next_game.make_move(chess_move);
-        
-                result.push((*chess_move, next_game.perft_helper(depth - 1)));
-            }
-        
-            result
-        }
-        
-        fn perft_helper(&mut self, depth: usize) -> usize {
-            if depth == 0 {
-                return 1;
-            }
-        
-            let moves = self.get_moves();
-            let mut result = 0;
-        
-            for chess_move in moves.iter() {
-                let mut next_game = self.clone();
-                next_game.make_move(chess_move);
-                result += next_game.perft_helper(depth - 1);
-            }
-        
-            result
-        }
+    /// Gets the position after a move without changing this one
+    ///
+    /// # Arguments
+    ///
+    /// * `chess_move` - A ChessMove generated by the get_moves function
+    pub fn peek_move(&self, chess_move: &ChessMove) -> Game {
+        let mut next_game = self.clone();
+        next_game.make_move(chess_move);
+        next_game
     }
 
-    // 333.39
-    #[test]
-    fn test_perft_start()
-    {
-        let mut curr_game = Game::new();
+    /// Gets the position where the side to move passes instead of moving, for null move
+    /// pruning. Any en passant square is lost, and since a pass isn't a legal move the
+    /// repetition history starts over from the new position
+    pub fn peek_null_move(&self) -> Game {
+        let mut next_game = self.clone();
+        next_game.key ^= next_game.state_key();
 
-        let values = curr_game.perft(5);
+        if next_game.turn == PieceColor::Black {
+            next_game.full_moves = next_game.full_moves.saturating_add(1);
+        }
+        next_game.halfmove_clock += 1;
+        next_game.turn = !next_game.turn;
+        next_game.en_passant = None;
 
-        let expected_set: Vec<(&str, usize)> = vec!(
-            ("a2a3", 181046),
-            ("b2b3", 215255),
-            ("c2c3", 222861),
-            ("d2d3", 328511),
-            ("e2e3", 402988),
-            ("f2f3", 178889),
-            ("g2g3", 217210),
-            ("h2h3", 181044),
-            ("a2a4", 217832),
-            ("b2b4", 216145),
-            ("c2c4", 240082),
-            ("d2d4", 361790),
-            ("e2e4", 405385),
-            ("f2f4", 198473),
-            ("g2g4", 214048),
-            ("h2h4", 218829),
-            ("b1a3", 198572),
-            ("b1c3", 234656),
-            ("g1f3", 233491),
-            ("g1h3", 198502),
-        );
+        next_game.key ^= next_game.state_key();
+        next_game.history = vec!(next_game.key);
+        next_game
+    }
 
-        let expected_total = 4865609;
+    /// Performs a move like make_move and reports what it did
+    ///
+    /// # Arguments
+    ///
+    /// * `chess_move` - A ChessMove generated by the get_moves function
+    pub fn make_move_info(&mut self, chess_move: &ChessMove) -> MoveInfo {
+        let mut info = MoveInfo{captured: None, is_en_passant: false, promotion: None, is_castle: false};
 
-        let mut total = 0;
-        for (chess_move, amount) in values {
-            assert!(expected_set.iter().any(|pair| pair == &(chess_move.to_string().as_str(), amount)));
-            total += amount;
+        match chess_move {
+            ChessMove::CastleKingside | ChessMove::CastleQueenside => info.is_castle = true,
+            ChessMove::Move(from, to) => {
+                info.is_en_passant = Some(*to) == self.en_passant && self.board.get(from).is_some_and(|&Piece{piece_type, color: _}| piece_type == PieceType::Pawn);
+                info.captured = if info.is_en_passant {
+                    self.board.get(&to.backward(&self.turn)).copied()
+                }
+                else {
+                    self.board.get(to).copied()
+                };
+            },
+            ChessMove::PawnPromote(_, to, piece_type) => {
+                info.captured = self.board.get(to).copied();
+                info.promotion = Some(*piece_type);
+            },
         }
 
-        assert!(total == expected_total);
+        self.make_move(chess_move);
+        info
+    }
+
+    /// Performs a move like make_move, returning what is needed to take it back with unmake_move
+    /// so a search can walk the tree in place instead of copying the game at every node
+    ///
+    /// # Arguments
+    ///
+    /// * `chess_move` - A ChessMove generated by the get_moves function
+    pub fn make_move_undoable(&mut self, chess_move: &ChessMove) -> MoveUndo {
+        let is_pawn_move = match chess_move {
+            ChessMove::CastleKingside | ChessMove::CastleQueenside => false,
+            ChessMove::Move(from, _) => self.board.get(from).is_some_and(|&Piece{piece_type, color: _}| piece_type == PieceType::Pawn),
+            ChessMove::PawnPromote(_, _, _) => true,
+        };
+
+        let castle_rights = self.castle_rights;
+        let en_passant = self.en_passant;
+        let full_moves = self.full_moves;
+        let halfmove_clock = self.halfmove_clock;
+        let key = self.key;
+
+        // make_move clears the history after captures and pawn moves, so keep it to put back
+        let is_capture = match chess_move {
+            ChessMove::CastleKingside | ChessMove::CastleQueenside => false,
+            ChessMove::Move(_, to) | ChessMove::PawnPromote(_, to, _) => self.board.get(to).is_some(),
+        };
+        let history = (is_pawn_move || is_capture).then(|| std::mem::take(&mut self.history));
+
+        let info = self.make_move_info(chess_move);
+
+        MoveUndo {chess_move: *chess_move, info, castle_rights, en_passant, full_moves, halfmove_clock, key, history}
+    }
+
+    /// Takes back the last move made with make_move_undoable, restoring the game exactly
+    pub fn unmake_move(&mut self, undo: MoveUndo) {
+        let mover = !self.turn;
+        let back_row = match mover {
+            PieceColor::White => 0,
+            PieceColor::Black => 7,
+        };
+
+        match undo.chess_move {
+            ChessMove::CastleKingside | ChessMove::CastleQueenside => {
+                let (king_to, rook_from, rook_to) = match undo.chess_move {
+                    ChessMove::CastleKingside => (6, 7, 5),
+                    _ => (2, 0, 3),
+                };
+
+                self.board.make_move(&Position::encode(back_row, king_to), &Position::encode(back_row, 4));
+                self.board.make_move(&Position::encode(back_row, rook_to), &Position::encode(back_row, rook_from));
+            },
+            ChessMove::Move(from, to) => {
+                self.board.make_move(&to, &from);
+
+                if let Some(captured) = undo.info.captured {
+                    let captured_position = if undo.info.is_en_passant { to.backward(&mover) } else { to };
+                    self.board.add_piece(captured, &captured_position);
+                }
+            },
+            ChessMove::PawnPromote(from, to, _) => {
+                self.board.remove_piece(&to);
+                self.board.add_piece(Piece{piece_type: PieceType::Pawn, color: mover}, &from);
+
+                if let Some(captured) = undo.info.captured {
+                    self.board.add_piece(captured, &to);
+                }
+            },
+        }
+
+        self.turn = mover;
+        self.castle_rights = undo.castle_rights;
+        self.en_passant = undo.en_passant;
+        self.full_moves = undo.full_moves;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.key = undo.key;
+
+        match undo.history {
+            Some(history) => self.history = history,
+            None => {
+                self.history.pop();
+            },
+        }
+    }
+
+    /// Removes the castling right tied to a rook's starting corner once anything moves from or to it
+    fn clear_corner_castle_rights(&mut self, position: &Position) {
+        match position.decode() {
+            (0, 0) => self.castle_rights[PieceColor::White as usize].queenside = false,
+            (0, 7) => self.castle_rights[PieceColor::White as usize].kingside = false,
+            (7, 0) => self.castle_rights[PieceColor::Black as usize].queenside = false,
+            (7, 7) => self.castle_rights[PieceColor::Black as usize].kingside = false,
+            _ => (),
+        }
+    }
+
+    fn move_piece(&mut self, from: &Position, to: &Position) {
+        if let Some(piece) = self.remove_piece(from) {
+            self.remove_piece(to);
+            self.add_piece(piece, to);
+        }
+    }
+
+    fn add_piece(&mut self, piece: Piece, position: &Position) {
+        self.key ^= piece_key(&piece, position);
+        if let Some(replaced) = self.board.add_piece(piece, position) {
+            self.key ^= piece_key(&replaced, position);
+        }
+    }
+
+    fn remove_piece(&mut self, position: &Position) -> Option<Piece> {
+        let removed = self.board.remove_piece(position);
+        if let Some(piece) = removed.as_ref() {
+            self.key ^= piece_key(piece, position);
+        }
+        removed
+    }
+
+    /// Hashes the side to move, castling rights and en passant square
+    fn state_key(&self) -> u64 {
+        let mut key = 0;
+
+        for color in [PieceColor::Black, PieceColor::White] {
+            let rights = self.castle_rights[color as usize];
+            if rights.kingside {
+                key ^= ZOBRIST_KEYS.castle_rights[color as usize][0];
+            }
+            if rights.queenside {
+                key ^= ZOBRIST_KEYS.castle_rights[color as usize][1];
+            }
+        }
+
+        if self.turn == PieceColor::Black {
+            key ^= ZOBRIST_KEYS.black_to_move;
+        }
+
+        if let Some(en_passant) = self.en_passant {
+            key ^= ZOBRIST_KEYS.en_passant[en_passant.column()];
+        }
+
+        key
+    }
+
+
+    /// Hashes everything that makes two positions identical for the repetition rules
+    fn position_key(&self) -> u64 {
+        let mut key = self.state_key();
+
+        for color in [PieceColor::Black, PieceColor::White] {
+            for (position, piece_type) in self.board.get_pieces(&color) {
+                key ^= piece_key(&Piece{piece_type, color}, &position);
+            }
+        }
+
+        key
+    }
+
+    /// Zobrist key of the current position, covering the pieces, side to move,
+    /// castling rights and en passant square but not the move counters
+    pub fn zobrist_hash(&self) -> u64 {
+        self.key
+    }
+
+    /// Number of times the current position has occurred, including now
+    pub fn repetition_count(&self) -> usize {
+        match self.history.last() {
+            Some(current) => self.history.iter().filter(|&key| key == current).count(),
+            None => 1,
+        }
+    }
+
+    /// Whether the current position has occurred at least three times with the same
+    /// side to move, castling rights and en passant square
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.repetition_count() >= 3
+    }
+
+    /// Whether no sequence of moves could end in checkmate: only kings and a single knight,
+    /// or only kings and bishops which all stand on the same square color
+    pub fn is_insufficient_material(&self) -> bool {
+        let mut knights = 0;
+        let mut bishup_square_colors = [false, false];
+
+        for color in [PieceColor::White, PieceColor::Black] {
+            for (position, piece_type) in self.board.get_pieces(&color) {
+                match piece_type {
+                    PieceType::King => (),
+                    PieceType::Knight => knights += 1,
+                    PieceType::Bishup => bishup_square_colors[position.square_color() as usize] = true,
+                    PieceType::Pawn | PieceType::Rook | PieceType::Queen => return false,
+                }
+            }
+        }
+
+        match (knights, bishup_square_colors) {
+            (0, [true, true]) => false,
+            (0, _) => true,
+            (1, [false, false]) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether fifty moves by each side have passed without a capture or pawn move
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    /// Gets the result of the game, preferring forced outcomes over claimable draws
+    pub fn status(&self) -> GameResult {
+        if !self.has_any_legal_move() {
+            return if self.is_in_check() { GameResult::Checkmate(self.turn) } else { GameResult::Stalemate };
+        }
+
+        let repetitions = self.repetition_count();
+
+        if self.is_insufficient_material() {
+            GameResult::InsufficientMaterial
+        }
+        else if repetitions >= 5 {
+            GameResult::Fivefold
+        }
+        else if self.halfmove_clock >= 150 {
+            GameResult::SeventyFiveMove
+        }
+        else if repetitions >= 3 {
+            GameResult::ThreefoldClaimable
+        }
+        else if self.halfmove_clock >= 100 {
+            GameResult::FiftyMoveClaimable
+        }
+        else {
+            GameResult::Ongoing
+        }
+    }
+
+    /// Whether the side to move could claim a draw while the game otherwise continues
+    pub fn is_draw_claimable(&self) -> bool {
+        matches!(self.status(), GameResult::FiftyMoveClaimable | GameResult::ThreefoldClaimable)
+    }
+
+    /// Renders a line of moves from the current position in SAN with move numbers,
+    /// counting moves from 1, e.g. "1. e4 e5 2. Nf3"
+    ///
+    /// # Arguments
+    ///
+    /// * `moves` - Moves to play in order, each legal after the ones before it
+    pub fn san_line(&self, moves: &[ChessMove]) -> Result<String> {
+        let mut game = self.clone();
+        let mut tokens = vec!();
+
+        for (index, chess_move) in moves.iter().enumerate() {
+            if !game.get_moves().contains(chess_move) {
+                return Err(eyre!("Move {} is not legal after {} moves of the line", chess_move, index));
+            }
+
+            match (game.turn, index) {
+                (PieceColor::White, _) => tokens.push(format!("{}.", game.move_number())),
+                (PieceColor::Black, 0) => tokens.push(format!("{}...", game.move_number())),
+                _ => (),
+            }
+
+            tokens.push(chess_move.to_san(&game));
+            game.make_move(chess_move);
+        }
+
+        Ok(tokens.join(" "))
+    }
+
+    /// Counts the leaf nodes `depth` moves deep below each legal move, sorted by the moves'
+    /// UCI strings so the output lines up with the divide of reference engines
+    pub fn perft_divide(&self, depth: usize) -> Vec<(ChessMove, u64)> {
+        if depth == 0 {
+            return vec!();
+        }
+
+        let mut result: Vec<(ChessMove, u64)> = self.get_moves().iter().map(|chess_move| {
+            (*chess_move, self.peek_move(chess_move).perft(depth - 1))
+        }).collect();
+
+        result.sort_by_key(|(chess_move, _)| chess_move.to_uci(&self.turn));
+        result
+    }
+
+    /// Runs perft and returns each move whose count differs from the reference divide
+    /// as (move, counted, expected). Missing moves on either side count as zero.
+    /// Reference moves may be in UCI notation or write castling as O-O, and any that
+    /// can't be parsed are an error
+    pub fn perft_compare(&self, depth: usize, expected: &[(&str, u64)]) -> Result<Vec<(ChessMove, u64, u64)>> {
+        let mut parsed: Vec<(ChessMove, u64)> = vec!();
+        let mut unparsed: Vec<&str> = vec!();
+        for (name, amount) in expected.iter() {
+            match self.parse_move(name) {
+                Some(chess_move) => parsed.push((chess_move, *amount)),
+                None => unparsed.push(name),
+            }
+        }
+
+        if !unparsed.is_empty() {
+            return Err(eyre!("Could not parse reference moves {}", unparsed.join(", ")));
+        }
+
+        // Moves are matched by their UCI names, the same ones perft_divide sorts by
+        let counts = self.perft_divide(depth);
+        let mut differences = vec!();
+
+        for (chess_move, count) in counts.iter() {
+            let move_string = chess_move.to_uci(&self.turn);
+            let expected_count = parsed.iter().find(|(expected_move, _)| expected_move.to_uci(&self.turn) == move_string).map_or(0, |(_, amount)| *amount);
+
+            if *count != expected_count {
+                differences.push((*chess_move, *count, expected_count));
+            }
+        }
+
+        for (expected_move, amount) in parsed.iter() {
+            if !counts.iter().any(|(chess_move, _)| chess_move.to_uci(&self.turn) == expected_move.to_uci(&self.turn)) {
+                differences.push((*expected_move, 0, *amount));
+            }
+        }
+
+        Ok(differences)
+    }
+
+    /// Counts all leaf nodes `depth` moves deep
+    pub fn perft(&self, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        let moves = self.get_moves();
+
+        // Leaf moves don't need to be made, which also keeps debug validation off the hottest path
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+
+        let mut result = 0;
+
+        for chess_move in moves.iter() {
+            let next_game = self.peek_move(chess_move);
+            result += next_game.perft(depth - 1);
+        }
+
+        result
+    }
+
+    pub fn print(&self) {
+        println!("{}'s Turn", self.turn);
+        println!("k:{}, q:{}, K:{}, Q:{}", self.castle_rights[0].kingside, self.castle_rights[0].queenside,self.castle_rights[1].kingside, self.castle_rights[1].queenside);
+
+        self.board.print();
+    }
+}
+
+/// The same text as print with the board uncolored
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}'s Turn", self.turn)?;
+        writeln!(f, "k:{}, q:{}, K:{}, Q:{}", self.castle_rights[0].kingside, self.castle_rights[0].queenside,self.castle_rights[1].kingside, self.castle_rights[1].queenside)?;
+        write!(f, "{}", self.board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play_moves(game: &mut Game, moves: &[&str]) {
+        for move_text in moves {
+            game.make_move(&ChessMove::from_str(move_text).expect("Could not parse move"));
+        }
+    }
+
+    #[test]
+    fn test_startpos() {
+        assert!(Game::startpos() == Game::from_fen(START_FEN).expect(""));
+        assert!(Game::new() == Game::startpos());
+    }
+
+    #[test]
+    fn test_threefold_claimable() {
+        let mut game = Game::new();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+        play_moves(&mut game, &shuffle);
+        assert!(game.repetition_count() == 2);
+        assert!(!game.is_threefold_repetition());
+        assert!(game.status() == GameResult::Ongoing);
+
+        play_moves(&mut game, &shuffle);
+        assert!(game.repetition_count() == 3);
+        assert!(game.is_threefold_repetition());
+        assert!(game.status() == GameResult::ThreefoldClaimable);
+        assert!(game.is_draw_claimable());
+    }
+
+    #[test]
+    fn test_fivefold_forced() {
+        let mut game = Game::new();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+        for _ in 0..3 {
+            play_moves(&mut game, &shuffle);
+        }
+        assert!(game.status() == GameResult::ThreefoldClaimable);
+
+        play_moves(&mut game, &shuffle);
+        assert!(game.repetition_count() == 5);
+        assert!(game.status() == GameResult::Fivefold);
+        assert!(!game.is_draw_claimable());
+    }
+
+    #[test]
+    fn test_repetition_reset_by_pawn_move() {
+        let mut game = Game::new();
+
+        play_moves(&mut game, &["g1f3", "g8f6", "f3g1", "f6g8", "e2e4"]);
+        assert!(game.repetition_count() == 1);
+        assert!(game.halfmove_clock == 0);
+    }
+
+    #[test]
+    fn test_fifty_move_claimable_and_seventy_five_forced() {
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").expect("");
+        assert!(game.status() == GameResult::Ongoing);
+
+        assert!(!game.is_fifty_move_draw());
+
+        play_moves(&mut game, &["a1a2"]);
+        assert!(game.halfmove_clock == 100);
+        assert!(game.is_fifty_move_draw());
+        assert!(game.to_fen() == "4k3/8/8/8/8/8/R7/4K3 b - - 100 80");
+        assert!(game.status() == GameResult::FiftyMoveClaimable);
+
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 149 105").expect("");
+        play_moves(&mut game, &["a1a2"]);
+        assert!(game.status() == GameResult::SeventyFiveMove);
+    }
+
+    #[test]
+    fn test_fifty_move_clock_reset_by_captures() {
+        let mut game = Game::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 99 80").expect("");
+        play_moves(&mut game, &["e5d6"]);
+        assert!(game.halfmove_clock == 0);
+        assert!(!game.is_fifty_move_draw());
+
+        let mut game = Game::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 99 80").expect("");
+        play_moves(&mut game, &["a7b8q"]);
+        assert!(game.halfmove_clock == 0);
+    }
+
+    #[test]
+    fn test_status_checkmate_and_stalemate() {
+        let game = Game::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1").expect("");
+        assert!(game.status() == GameResult::Checkmate(PieceColor::Black));
+
+        let game = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").expect("");
+        assert!(game.status() == GameResult::Stalemate);
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        for fen in ["8/8/4k3/8/8/3K4/8/8 w - - 0 1", "8/8/4k3/8/8/3KN3/8/8 w - - 0 1", "8/8/4k3/2b5/8/3KB3/8/8 b - - 0 1", "8/8/4k3/8/8/3K4/6B1/5B2 w - - 0 1"] {
+            let game = Game::from_fen(fen).expect("");
+            assert!(game.is_insufficient_material(), "{}", fen);
+            assert!(game.status() == GameResult::InsufficientMaterial);
+        }
+
+        for fen in ["8/8/4k3/8/8/3KNN2/8/8 w - - 0 1", "8/8/2b1k3/8/8/3KB3/8/8 b - - 0 1", "8/8/4kn2/8/8/3KB3/8/8 w - - 0 1", "8/4p3/4k3/8/8/3K4/8/8 w - - 0 1"] {
+            let game = Game::from_fen(fen).expect("");
+            assert!(!game.is_insufficient_material(), "{}", fen);
+            assert!(game.status() == GameResult::Ongoing);
+        }
+    }
+
+    #[test]
+    fn test_captures() {
+        let game = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").expect("");
+        assert!(game.captures().len() == 8);
+
+        let game = Game::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").expect("");
+        let captures = game.captures();
+        assert!(captures.len() == 1);
+        assert!(captures[0].to_string() == "e5f6");
+    }
+
+    #[test]
+    fn test_checks() {
+        let game = Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").expect("");
+        let checks = game.checks();
+        assert!(checks.len() == 2);
+
+        for chess_move in checks.iter() {
+            let next_game = game.peek_move(chess_move);
+            let king = next_game.board.get_king(&next_game.turn).expect("No king found");
+            assert!(next_game.board.has_check(&king, &next_game.turn));
+        }
+    }
+
+    #[test]
+    fn test_promotion_capture_clears_castle_rights() {
+        let mut game = Game::from_fen("r3k2r/1P6/8/8/8/8/8/4K3 w kq - 0 1").expect("");
+        play_moves(&mut game, &["b7a8q"]);
+
+        assert!(game.board.get(&Position::encode(7, 0)) == Some(&Piece{piece_type: PieceType::Queen, color: PieceColor::White}));
+        assert!(!game.castle_rights[PieceColor::Black as usize].queenside);
+        assert!(game.castle_rights[PieceColor::Black as usize].kingside);
+    }
+
+    #[test]
+    fn test_ordered_moves() {
+        let game = Game::from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").expect("");
+        let moves = game.ordered_moves();
+
+        assert!(moves == game.ordered_moves());
+        assert!(moves.len() == game.get_moves().len());
+
+        for pair in moves.windows(2) {
+            assert!(game.move_order_key(&pair[0]) < game.move_order_key(&pair[1]));
+        }
+
+        let move_strings: Vec<String> = moves.iter().take(4).map(|chess_move| chess_move.to_string()).collect();
+        assert!(move_strings == vec!["b1d2", "b1a3", "b1c3", "c1d2"]);
+
+        let promotions: Vec<String> = moves.iter().filter(|chess_move| matches!(chess_move, ChessMove::PawnPromote(_, _, _))).map(|chess_move| chess_move.to_string()).collect();
+        assert!(promotions == vec!["d7c8n", "d7c8b", "d7c8r", "d7c8q"]);
+    }
+
+    #[test]
+    fn test_en_passant_pinned_on_file() {
+        let game = Game::from_fen("4r2k/8/8/3pP3/8/8/8/4K3 w - d6 0 2").expect("");
+        let moves: Vec<String> = game.get_moves().iter().map(|chess_move| chess_move.to_string()).collect();
+
+        assert!(!moves.contains(&"e5d6".to_owned()));
+        assert!(moves.contains(&"e5e6".to_owned()));
+    }
+
+    #[test]
+    fn test_en_passant_pinned_on_rank() {
+        let game = Game::from_fen("7k/8/8/K2pP2r/8/8/8/8 w - d6 0 2").expect("");
+        let moves: Vec<String> = game.get_moves().iter().map(|chess_move| chess_move.to_string()).collect();
+
+        assert!(!moves.contains(&"e5d6".to_owned()));
+        assert!(moves.contains(&"e5e6".to_owned()));
+    }
+
+    #[test]
+    fn test_san_line() {
+        let game = Game::new();
+        let line: Vec<ChessMove> = ["e2e4", "e7e5", "g1f3"].iter().map(|name| ChessMove::from_str(name).expect("")).collect();
+
+        assert_eq!(game.san_line(&line).expect(""), "1. e4 e5 2. Nf3");
+        assert!(game.san_line(&line[1..]).is_err());
+
+        let game = Game::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").expect("");
+        assert_eq!(game.san_line(&line[1..]).expect(""), "1... e5 2. Nf3");
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w Kq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 37 61",
+        ] {
+            let game = Game::from_fen(fen).expect("");
+            let bytes = game.to_bytes();
+
+            assert!(bytes.len() < fen.len());
+            assert!(Game::from_bytes(&bytes).expect("") == game);
+        }
+
+        assert!(Game::from_bytes(&[0u8; 37]).is_err());
+
+        let mut bytes = Game::new().to_bytes();
+        bytes[33] = 64;
+        assert!(Game::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_parse_move() {
+        let game = Game::from_fen("4k3/4P3/8/8/8/8/8/R3K2R w KQ - 0 1").expect("");
+        let e7 = Position::from_str("e7").expect("");
+        let e8 = Position::from_str("e8").expect("");
+
+        assert!(ChessMove::from_str("e7e8") == Some(ChessMove::Move(e7, e8)));
+        assert!(game.parse_move("e7e8") == Some(ChessMove::PawnPromote(e7, e8, PieceType::Queen)));
+        assert!(game.parse_move("e7e8n") == Some(ChessMove::PawnPromote(e7, e8, PieceType::Knight)));
+        assert!(game.parse_move("e1g1") == Some(ChessMove::CastleKingside));
+        assert!(game.parse_move("e1c1") == Some(ChessMove::CastleQueenside));
+        assert!(game.parse_move("e1f1") == ChessMove::from_str("e1f1"));
+        assert!(game.parse_move("e7").is_none());
+
+        assert!(ChessMove::CastleKingside.to_uci(&PieceColor::White) == "e1g1");
+        assert!(ChessMove::CastleQueenside.to_uci(&PieceColor::Black) == "e8c8");
+        assert!(ChessMove::PawnPromote(e7, e8, PieceType::Knight).to_uci(&PieceColor::White) == "e7e8n");
+    }
+
+    #[test]
+    fn test_gives_check() {
+        // The knight uncovers the rook's file
+        let game = Game::from_fen("4k3/8/8/8/4N3/8/8/4RK2 w - - 0 1").expect("");
+        assert!(game.gives_check(&ChessMove::from_str("e4c5").expect("")));
+        assert!(!game.gives_check(&ChessMove::from_str("e1d1").expect("")));
+
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").expect("");
+        let mate = ChessMove::from_str("a1a8").expect("");
+        assert!(game.gives_check(&mate));
+        assert!(game.peek_move(&mate).is_checkmate());
+        assert!(mate.to_san(&game) == "Ra8#");
+    }
+
+    #[test]
+    fn test_to_fen_round_trip() {
+        let fen = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+        let mut game = Game::from_fen(fen).expect("");
+        assert!(game.to_fen() == fen);
+
+        play_moves(&mut game, &["O-O", "f2d1"]);
+        assert!(game.to_fen() == "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1N1PP/RNBn1RK1 w - - 0 9");
+    }
+
+    #[test]
+    fn test_from_fen_whitespace() {
+        let game = Game::new();
+
+        assert!(Game::from_fen(&format!("  {}\n", START_FEN)).expect("") == game);
+        assert!(Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR  w KQkq -\t0  1").expect("") == game);
+        assert!(Game::from_fen(&format!("{} e2e4 e7e5", START_FEN)).expect("") == game);
+        assert!(Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0").is_err());
+        assert!(Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 x").is_err());
+    }
+
+    #[test]
+    fn test_incremental_key_matches_recompute() {
+        // En passant, a promotion capture and castling on both sides
+        let moves = [
+            "e2e4", "d7d5", "e4e5", "f7f5", "e5f6", "b8c6", "f6g7", "c8e6", "g7h8q", "d8d7",
+            "g1f3", "O-O-O", "f1e2", "c8b8", "O-O", "d5d4", "c2c4", "d4c3", "b2c3",
+        ];
+        let mut game = Game::new();
+
+        for move_str in moves {
+            let chess_move = ChessMove::from_str(move_str).expect("");
+            assert!(game.get_moves().contains(&chess_move), "{} is not legal", move_str);
+            game.make_move(&chess_move);
+            assert!(game.key == game.position_key(), "Key diverged after {}", move_str);
+        }
+    }
+
+    #[test]
+    fn test_is_quiet() {
+        // Locked pawn chains with nothing to take
+        assert!(Game::from_fen("4k3/8/8/2p1p3/2P1P3/8/8/4K3 w - - 0 1").expect("").is_quiet());
+
+        // The knight can take a hanging rook
+        assert!(!Game::from_fen("4k3/8/8/3r4/8/4N3/8/4K3 w - - 0 1").expect("").is_quiet());
+
+        // Taking the pawn loses the queen to the other pawn
+        assert!(Game::from_fen("4k3/8/2p5/3p4/8/8/3Q4/4K3 w - - 0 1").expect("").is_quiet());
+
+        // In check, and a pawn about to promote
+        assert!(!Game::from_fen("4k3/8/8/8/8/8/8/r3K3 w - - 0 1").expect("").is_quiet());
+        assert!(!Game::from_fen("7k/2P5/8/8/8/8/8/4K3 w - - 0 1").expect("").is_quiet());
+    }
+
+    #[test]
+    fn test_peek_null_move() {
+        let game = Game::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").expect("");
+        let null_game = game.peek_null_move();
+        assert!(null_game.to_fen() == "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR b KQkq - 1 3");
+        assert!(null_game.zobrist_hash() == Game::from_fen(&null_game.to_fen()).expect("").zobrist_hash());
+
+        let passed_back = null_game.peek_null_move();
+        assert!(passed_back.turn == PieceColor::White && passed_back.full_moves == 4);
+        assert!(passed_back.zobrist_hash() == Game::from_fen(&passed_back.to_fen()).expect("").zobrist_hash());
+    }
+
+    #[test]
+    fn test_mvv_lva() {
+        // The pawn, knight and queen can all take the rook
+        let game = Game::from_fen("4k3/8/8/3r4/2P5/4N3/8/3QK3 w - - 0 1").expect("");
+        let score = |name: &str| game.mvv_lva(&ChessMove::from_str(name).expect(""));
+        assert!(score("c4d5") > score("e3d5"));
+        assert!(score("e3d5") > score("d1d5"));
+        assert!(score("d1d5") > score("e3c2"));
+        assert!(score("e3c2") == 0);
+
+        let game = Game::from_fen("7k/2P5/8/8/8/8/8/4K3 w - - 0 1").expect("");
+        let score = |name: &str| game.mvv_lva(&ChessMove::from_str(name).expect(""));
+        assert!(score("c7c8q") > score("c7c8n"));
+        assert!(score("c7c8n") > score("e1e2"));
+    }
+
+    #[test]
+    fn test_play_line() {
+        let expected: Vec<ChessMove> = ["e2e4", "e7e5", "g1f3"].iter().map(|name| ChessMove::from_str(name).expect("")).collect();
+
+        for line in ["1. e4 e5 2. Nf3", "1.e4 e5 2.Nf3", "e2e4 e7e5 g1f3"] {
+            let mut game = Game::new();
+            assert!(game.play_line(line).expect("") == expected);
+            assert!(game.move_number() == 2 && game.turn == PieceColor::Black);
+        }
+
+        let mut game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").expect("");
+        assert!(game.parse_san("Ra8#") == ChessMove::from_str("a1a8"));
+        assert!(game.play_line("1. Rb8 Qd8").is_err());
+
+        for (line, castle) in [("0-0", ChessMove::CastleKingside), ("1.0-0-0", ChessMove::CastleQueenside)] {
+            let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").expect("");
+            assert!(game.play_line(line).expect("") == [castle]);
+        }
+
+        let mut game = Game::new();
+        assert!(game.play_line("1. e4 1... e5").expect("").len() == 2);
+    }
+
+    #[test]
+    fn test_random_games_keep_invariants() {
+        let mut rng = StdRng::seed_from_u64(1428);
+
+        for _ in 0..100 {
+            let mut game = Game::new();
+
+            for chess_move in Game::random_legal_game(&mut rng, 200) {
+                game.make_move(&chess_move);
+
+                for color in [PieceColor::White, PieceColor::Black] {
+                    assert!(game.board.get_pieces(&color).iter().filter(|(_, piece_type)| *piece_type == PieceType::King).count() == 1);
+                }
+                assert!(game.validate().is_ok(), "{}", game.to_fen());
+
+                let fen = game.to_fen();
+                let reloaded = Game::from_fen(&fen).expect("");
+                assert!(reloaded.to_fen() == fen && reloaded.key == game.key, "{}", fen);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ply_and_move_number() {
+        let mut game = Game::new();
+        assert!(game.ply() == 0 && game.move_number() == 1);
+
+        game.make_move(&ChessMove::from_str("e2e4").expect(""));
+        assert!(game.ply() == 1 && game.move_number() == 1);
+
+        game.make_move(&ChessMove::from_str("e7e5").expect(""));
+        assert!(game.ply() == 2 && game.move_number() == 2);
+        assert!(game.turn == PieceColor::White);
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 40").expect("");
+        assert!(game.ply() == 79 && game.move_number() == 40);
+    }
+
+    #[test]
+    fn test_to_san() {
+        let game = Game::from_fen("1r5k/P7/8/8/8/5N2/8/1N2K3 w - - 0 1").expect("");
+
+        assert_eq!(ChessMove::from_str("a7b8q").expect("").to_san(&game), "axb8=Q+");
+        assert_eq!(ChessMove::from_str("b1d2").expect("").to_san(&game), "Nbd2");
+        assert_eq!(ChessMove::from_str("e1f2").expect("").to_san(&game), "Kf2");
+
+        // Rooks on one file are told apart by rank
+        let game = Game::from_fen("7k/8/8/R7/8/8/8/R5K1 w - - 0 1").expect("");
+        assert_eq!(ChessMove::from_str("a1a3").expect("").to_san(&game), "R1a3");
+        assert_eq!(ChessMove::from_str("a5a3").expect("").to_san(&game), "R5a3");
+
+        // A rival on the same file and another on the same rank need the whole square
+        let game = Game::from_fen("8/2k5/8/8/4Q2Q/8/8/K6Q w - - 0 1").expect("");
+        assert_eq!(ChessMove::from_str("h4e1").expect("").to_san(&game), "Qh4e1");
+        assert_eq!(ChessMove::from_str("e4e1").expect("").to_san(&game), "Qee1");
+    }
+
+    #[test]
+    fn test_moves_for_piece_type() {
+        let game = Game::new();
+        let knight_moves: Vec<String> = game.moves_for_piece_type(PieceType::Knight).iter().map(|chess_move| chess_move.to_string()).collect();
+
+        assert!(knight_moves.len() == 4);
+        for name in ["b1a3", "b1c3", "g1f3", "g1h3"] {
+            assert!(knight_moves.iter().any(|chess_move| chess_move == name));
+        }
+
+        let game = Game::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1").expect("");
+        let king_moves = game.moves_for_piece_type(PieceType::King);
+        assert!(king_moves.len() == 4);
+        assert!(king_moves.contains(&ChessMove::CastleKingside) && king_moves.contains(&ChessMove::CastleQueenside));
+        assert!(game.moves_for_piece_type(PieceType::Rook).len() == 5);
+    }
+
+    #[test]
+    fn test_pseudo_legal_moves() {
+        // The e2 rook is pinned against the king by the e8 rook
+        let game = Game::from_fen("4r2k/8/8/8/8/8/4R3/4K3 w - - 0 1").expect("");
+        let legal = game.get_moves();
+        let pseudo_legal = game.pseudo_legal_moves();
+
+        assert!(legal.iter().all(|chess_move| pseudo_legal.contains(chess_move)));
+
+        let exposing: Vec<String> = pseudo_legal.iter().filter(|chess_move| !legal.contains(chess_move)).map(|chess_move| chess_move.to_string()).collect();
+        assert!(exposing.len() == 7);
+        assert!(exposing.iter().all(|chess_move| chess_move.starts_with("e2") && !chess_move.starts_with("e2e")));
+        assert!(pseudo_legal.iter().all(|chess_move| game.is_legal_after(chess_move) == legal.contains(chess_move)));
+    }
+
+    #[test]
+    fn test_has_any_legal_move() {
+        let fens = [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+            "R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1",
+            "k7/8/1QK5/8/8/8/8/8 b - - 0 1",
+        ];
+
+        for fen in fens {
+            let game = Game::from_fen(fen).expect("");
+            assert_eq!(game.has_any_legal_move(), !game.get_moves().is_empty());
+        }
+
+        assert!(Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").expect("").is_stalemate());
+        assert!(Game::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").expect("").is_checkmate());
+        assert!(!Game::new().is_checkmate() && !Game::new().is_stalemate());
+    }
+
+    #[test]
+    fn test_make_unmake_move() {
+        // Castling both ways, en passant, and promotions with and without a capture
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ] {
+            let original = Game::from_fen(fen).expect("");
+            let mut game = original.clone();
+
+            for chess_move in original.get_moves() {
+                let undo = game.make_move_undoable(&chess_move);
+                assert!(game == original.peek_move(&chess_move), "{} from {}", chess_move, fen);
+
+                for reply in game.get_moves() {
+                    let before_reply = game.clone();
+                    let reply_undo = game.make_move_undoable(&reply);
+                    game.unmake_move(reply_undo);
+                    assert!(game == before_reply, "{} {} from {}", chess_move, reply, fen);
+                }
+
+                game.unmake_move(undo);
+                assert!(game == original && game.to_fen() == fen, "{} from {}", chess_move, fen);
+            }
+        }
+
+        // A whole game taken back move by move passes through every position again
+        let mut rng = StdRng::seed_from_u64(1526);
+        let moves = Game::random_legal_game(&mut rng, 120);
+        let mut game = Game::new();
+        let mut positions = vec!();
+        let mut undos = vec!();
+
+        for chess_move in moves.iter() {
+            positions.push(game.clone());
+            undos.push(game.make_move_undoable(chess_move));
+        }
+
+        while let Some(undo) = undos.pop() {
+            game.unmake_move(undo);
+            let position = positions.pop().expect("");
+            assert!(game == position && game.to_fen() == position.to_fen());
+        }
+        assert!(game == Game::new());
+    }
+
+    #[test]
+    fn test_display() {
+        let mut game = Game::from_fen("4k2r/8/8/8/8/8/8/R3K3 w Qk - 0 1").expect("");
+        game.play_line("Ra8+").expect("");
+
+        let expected = "\
+b's Turn
+k:true, q:false, K:false, Q:false
+8  R           k        r \n\
+7                         \n\
+6                         \n\
+5                         \n\
+4                         \n\
+3                         \n\
+2                         \n\
+1              K          \n\
+\x20  a  b  c  d  e  f  g  h \n";
+        assert_eq!(game.to_string(), expected);
+    }
+
+    #[test]
+    fn test_perft_divide() {
+        let game = Game::new();
+        assert!(game.perft(3) == 8902);
+        assert!(game.perft(0) == 1 && game.perft_divide(0).is_empty());
+
+        let divide = game.perft_divide(3);
+        assert!(divide.len() == 20);
+        assert!(divide.iter().map(|(_, count)| count).sum::<u64>() == 8902);
+        assert!(divide.windows(2).all(|pair| pair[0].0.to_uci(&game.turn) < pair[1].0.to_uci(&game.turn)));
+        assert!(divide[0] == (ChessMove::from_str("a2a3").expect(""), 380));
+
+        // Castling sorts by its king move
+        let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").expect("");
+        let names: Vec<String> = game.perft_divide(1).iter().map(|(chess_move, _)| chess_move.to_uci(&game.turn)).collect();
+        assert!(names.iter().position(|name| name == "e1c1") < names.iter().position(|name| name == "e1d1"));
+        assert!(names.iter().position(|name| name == "e1f2") < names.iter().position(|name| name == "e1g1"));
+    }
+
+    #[test]
+    fn test_perft_black_to_move() {
+        // Mirror of the chessprogramming.org position 4, so Black castles, promotes and captures en passant
+        let fen = "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1";
+        let game = Game::from_fen(fen).expect("");
+
+        assert!(game.to_fen() == fen);
+        assert!(game.perft(1) == 6);
+        assert!(game.perft(2) == 264);
+        assert!(game.perft(3) == 9467);
+        assert!(game.perft(4) == 422333);
+    }
+
+    #[test]
+    fn test_rook_off_corner_keeps_other_castle_right() {
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").expect("");
+        play_moves(&mut game, &["h1g1", "h8g8", "g1h1", "g8h8"]);
+
+        assert!(!game.castle_rights[PieceColor::White as usize].kingside && game.castle_rights[PieceColor::White as usize].queenside);
+        assert!(!game.castle_rights[PieceColor::Black as usize].kingside && game.castle_rights[PieceColor::Black as usize].queenside);
+        assert!(game.get_moves().contains(&ChessMove::CastleQueenside));
+    }
+
+    #[test]
+    fn test_rook_off_home_rank_keeps_castle_rights() {
+        // Second rooks on the a and h files move without touching the home rooks
+        let mut game = Game::from_fen("r3k2r/8/r6r/8/8/R6R/8/R3K2R w KQkq - 0 1").expect("");
+        play_moves(&mut game, &["a3a4", "a6a5", "h3h4", "h6h5"]);
+
+        assert!(game.castle_rights.iter().all(|rights| rights.kingside && rights.queenside));
+        assert!(game.get_moves().contains(&ChessMove::CastleQueenside) && game.get_moves().contains(&ChessMove::CastleKingside));
+    }
+
+    #[test]
+    fn test_make_move_info() {
+        let mut game = Game::from_fen("r3k2r/8/8/3pP3/8/8/N7/R3K2R w KQkq d6 0 1").expect("");
+
+        let info = game.make_move_info(&ChessMove::from_str("e5d6").expect(""));
+        assert!(info == MoveInfo{captured: Some(Piece{piece_type: PieceType::Pawn, color: PieceColor::Black}), is_en_passant: true, promotion: None, is_castle: false});
+
+        let info = game.make_move_info(&ChessMove::from_str("a8a2").expect(""));
+        assert!(info.is_capture() && info.captured == Some(Piece{piece_type: PieceType::Knight, color: PieceColor::White}) && !info.is_en_passant);
+
+        assert!(game.get_moves().contains(&ChessMove::CastleKingside));
+        let info = game.make_move_info(&ChessMove::CastleKingside);
+        assert!(info == MoveInfo{captured: None, is_en_passant: false, promotion: None, is_castle: true});
+    }
+
+    #[test]
+    fn test_capturing_rook_off_home_square_keeps_castle_rights() {
+        let mut game = Game::from_fen("1r2k2r/P7/8/7r/8/8/8/4K2R w Kk - 0 1").expect("");
+        play_moves(&mut game, &["h1h5"]);
+        assert!(game.castle_rights[PieceColor::Black as usize].kingside);
+
+        let mut game = Game::from_fen("1r2k2r/P7/8/7r/8/8/8/4K2R w Kk - 0 1").expect("");
+        play_moves(&mut game, &["a7b8q"]);
+        assert!(game.castle_rights[PieceColor::Black as usize].kingside);
+        assert!(game.castle_rights[PieceColor::White as usize].kingside);
+    }
+
+    #[test]
+    fn test_peek_move() {
+        let game = Game::new();
+        let chess_move = ChessMove::from_str("e2e4").expect("");
+        let peeked = game.peek_move(&chess_move);
+
+        let mut made = game.clone();
+        made.make_move(&chess_move);
+
+        assert!(game == Game::new());
+        assert!(peeked == made);
+    }
+
+    #[test]
+    fn test_checkers() {
+        assert!(Game::new().checkers().is_empty());
+
+        // The rook on e1 and the knight on f3 both give check
+        let game = Game::from_fen("4k3/8/8/8/8/5n2/8/4r1K1 w - - 0 1").expect("");
+        let checkers = game.checkers();
+        assert!(checkers.len() == 2);
+        assert!(checkers.contains(&Position::from_str("e1").expect("")));
+        assert!(checkers.contains(&Position::from_str("f3").expect("")));
+    }
+
+    #[test]
+    fn test_check_evasions() {
+        let fens = [
+            // Single check from a slider which can be blocked or captured
+            "4k3/8/8/8/8/2n5/3PB3/r3K2R w K - 0 1",
+            // Double check, only the king may move
+            "4k3/8/8/8/8/5n2/8/4r1K1 w - - 0 1",
+            // Check from a pawn which can be captured en passant
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            // Knight check with a pinned defender
+            "4k3/8/8/8/1b6/8/3N2n1/4K3 w - - 0 1",
+        ];
+
+        for fen in fens {
+            let game = Game::from_fen(fen).expect("");
+            assert!(!game.checkers().is_empty());
+
+            let mut evasions = game.ordered_moves();
+            let mut filtered: Vec<ChessMove> = game.pseudo_legal_moves().into_iter().filter(|chess_move| game.is_legal_after(chess_move)).collect();
+            filtered.sort_by_key(|chess_move| game.move_order_key(chess_move));
+            evasions.sort_by_key(|chess_move| game.move_order_key(chess_move));
+
+            assert_eq!(evasions, filtered);
+        }
+    }
+
+    fn assert_matches_filtered_moves(game: &Game, depth: usize) {
+        let mut moves = game.get_moves();
+        let mut filtered: Vec<ChessMove> = game.pseudo_legal_moves().into_iter().filter(|chess_move| game.is_legal_after(chess_move)).collect();
+        moves.sort_by_key(|chess_move| game.move_order_key(chess_move));
+        filtered.sort_by_key(|chess_move| game.move_order_key(chess_move));
+        assert_eq!(moves, filtered, "{}", game.to_fen());
+
+        if depth > 1 {
+            for chess_move in moves.iter() {
+                assert_matches_filtered_moves(&game.peek_move(chess_move), depth - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pin_aware_generation_matches_filtered_moves() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        ] {
+            assert_matches_filtered_moves(&Game::from_fen(fen).expect(""), 3);
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Game::new().validate().is_ok());
+        assert!(Game::from_fen("8/8/8/8/8/8/8/4K3 w - - 0 1").expect("").validate().is_err());
+        // Black to move while White's king stands in check means White moved into it
+        assert!(Game::from_fen("4k3/8/8/8/8/8/4r3/4K3 b - - 0 1").expect("").validate().is_err());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "illegal position")]
+    fn test_make_move_panics_on_illegal_move_in_debug() {
+        let mut game = Game::from_fen("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1").expect("");
+        play_moves(&mut game, &["e2d3"]);
+    }
+
+    #[test]
+    fn test_perft_compare_finds_tampered_entry() {
+        let game = Game::new();
+        let counts: Vec<(String, u64)> = game.perft_divide(2).iter().map(|(chess_move, count)| (chess_move.to_string(), *count)).collect();
+        let mut expected: Vec<(&str, u64)> = counts.iter().map(|(name, count)| (name.as_str(), *count)).collect();
+
+        assert_eq!(game.perft_compare(2, &expected).expect(""), vec!());
+        assert!(game.perft(2) == 400);
+
+        let tampered = expected.iter_mut().find(|(name, _)| *name == "e2e4").expect("");
+        tampered.1 += 1;
+
+        let differences = game.perft_compare(2, &expected).expect("");
+        assert!(differences.len() == 1);
+        assert!(differences[0] == (ChessMove::from_str("e2e4").expect(""), 20, 21));
+
+        // Castling matches whether the reference writes the king's move or O-O
+        let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").expect("");
+        let counts: Vec<(String, u64)> = game.perft_divide(2).iter().map(|(chess_move, count)| (chess_move.to_uci(&game.turn), *count)).collect();
+        let mut expected: Vec<(&str, u64)> = counts.iter().map(|(name, count)| (name.as_str(), *count)).collect();
+        assert_eq!(game.perft_compare(2, &expected).expect(""), vec!());
+
+        expected.iter_mut().find(|(name, _)| *name == "e1g1").expect("").0 = "O-O";
+        assert_eq!(game.perft_compare(2, &expected).expect(""), vec!());
+
+        expected.push(("castle", 1));
+        assert!(game.perft_compare(2, &expected).is_err());
+    }
+
+    // 333.39
+    #[test]
+    fn test_perft_start()
+    {
+        let curr_game = Game::new();
+
+
+        let expected_set: Vec<(&str, u64)> = vec!(
+            ("a2a3", 181046),
+            ("b2b3", 215255),
+            ("c2c3", 222861),
+            ("d2d3", 328511),
+            ("e2e3", 402988),
+            ("f2f3", 178889),
+            ("g2g3", 217210),
+            ("h2h3", 181044),
+            ("a2a4", 217832),
+            ("b2b4", 216145),
+            ("c2c4", 240082),
+            ("d2d4", 361790),
+            ("e2e4", 405385),
+            ("f2f4", 198473),
+            ("g2g4", 214048),
+            ("h2h4", 218829),
+            ("b1a3", 198572),
+            ("b1c3", 234656),
+            ("g1f3", 233491),
+            ("g1h3", 198502),
+        );
+
+        let expected_total = 4865609;
+
+        assert_eq!(curr_game.perft_compare(5, &expected_set).expect(""), vec!());
+        assert!(expected_set.iter().map(|(_, amount)| amount).sum::<u64>() == expected_total);
     }
 
     #[test]
     fn test_perft_pos5()
     {
-        let mut curr_game = Game::from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").expect("");
-        let values = curr_game.perft(4);
+        let curr_game = Game::from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").expect("");
 
-        let expected_set: Vec<(&str, usize)> = vec!(
+        let expected_set: Vec<(&str, u64)> = vec!(
             ("a2a3", 46833),
             ("b2b3", 46497),
             ("c2c3", 49406),
@@ -622,12 +2274,7 @@ mod tests {
 
         let expected_total = 2103487;
 
-        let mut total = 0;
-        for (chess_move, amount) in values {
-            assert!(expected_set.iter().any(|pair| pair == &(chess_move.to_string().as_str(), amount)));
-            total += amount;
-        }
-
-        assert!(total == expected_total);
+        assert_eq!(curr_game.perft_compare(4, &expected_set).expect(""), vec!());
+        assert!(expected_set.iter().map(|(_, amount)| amount).sum::<u64>() == expected_total);
     }
 }
\ No newline at end of file
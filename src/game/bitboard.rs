@@ -0,0 +1,322 @@
+use super::board::{Board, BoardLike};
+use super::piece::*;
+use super::position::*;
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+const FILE_B: u64 = FILE_A << 1;
+const FILE_G: u64 = FILE_A << 6;
+const FILE_H: u64 = FILE_A << 7;
+
+/// A board kept as one bit per square, a1 as bit 0 through h8 as bit 63, so attacks can be
+/// found a whole ray at a time with shifts. The pieces are also kept square by square so
+/// get can hand out references the way Board does
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BitBoard {
+    // Squares each color occupies, by PieceColor
+    occupancy: [u64; 2],
+    // Squares of each color's pieces of each type, by PieceColor then PieceType - 1
+    pieces: [[u64; 6]; 2],
+    squares: [Option<Piece>; 64],
+}
+
+impl BitBoard {
+    pub fn default() -> BitBoard {
+        BitBoard {
+            occupancy: [0; 2],
+            pieces: [[0; 6]; 2],
+            squares: [None; 64],
+        }
+    }
+
+    pub fn from_board(board: &Board) -> BitBoard {
+        let mut bitboard = BitBoard::default();
+
+        for row in 0usize..=7usize {
+            for column in 0usize..=7usize {
+                let position = Position::encode(row, column);
+                if let Some(piece) = board.get(&position) {
+                    bitboard.add_piece(*piece, &position);
+                }
+            }
+        }
+
+        bitboard
+    }
+
+    /// Squares occupied by a color's pieces
+    pub fn occupancy(&self, player_color: &PieceColor) -> u64 {
+        self.occupancy[*player_color as usize]
+    }
+
+    /// Squares occupied by either color
+    pub fn occupied(&self) -> u64 {
+        self.occupancy[0] | self.occupancy[1]
+    }
+
+    /// Squares of a color's pieces of one type
+    pub fn pieces(&self, piece_type: PieceType, player_color: &PieceColor) -> u64 {
+        self.pieces[*player_color as usize][piece_type as usize - 1]
+    }
+
+    pub fn add_piece(&mut self, piece: Piece, position: &Position) -> Option<Piece> {
+        let replaced = self.remove_piece(position);
+        let bit = square_bit(position);
+
+        self.occupancy[piece.color as usize] |= bit;
+        self.pieces[piece.color as usize][piece.piece_type as usize - 1] |= bit;
+        self.squares[position.index()] = Some(piece);
+
+        replaced
+    }
+
+    pub fn remove_piece(&mut self, position: &Position) -> Option<Piece> {
+        let removed = self.squares[position.index()].take();
+
+        if let Some(piece) = removed {
+            let bit = square_bit(position);
+            self.occupancy[piece.color as usize] &= !bit;
+            self.pieces[piece.color as usize][piece.piece_type as usize - 1] &= !bit;
+        }
+
+        removed
+    }
+
+    pub fn make_move(&mut self, from: &Position, to: &Position) -> Option<Piece> {
+        let piece = self.remove_piece(from)?;
+        self.add_piece(piece, to)
+    }
+
+    pub fn get_king(&self, player_color: &PieceColor) -> Option<Position> {
+        bit_positions(self.pieces(PieceType::King, player_color)).next()
+    }
+
+    fn bishup_attacks(&self, position: &Position) -> u64 {
+        let (bit, empty) = (square_bit(position), !self.occupied());
+        slide(bit, north_east, empty) | slide(bit, north_west, empty) | slide(bit, south_east, empty) | slide(bit, south_west, empty)
+    }
+
+    fn rook_attacks(&self, position: &Position) -> u64 {
+        let (bit, empty) = (square_bit(position), !self.occupied());
+        slide(bit, north, empty) | slide(bit, south, empty) | slide(bit, east, empty) | slide(bit, west, empty)
+    }
+
+    /// Turns the squares a piece attacks into the squares it could move to
+    fn move_targets(&self, attacks: u64, player_color: &PieceColor, get_captures_only: bool) -> Vec<Position> {
+        let targets = if get_captures_only {
+            attacks & self.occupancy(&!*player_color)
+        } else {
+            attacks & !self.occupancy(player_color)
+        };
+
+        bit_positions(targets).collect()
+    }
+}
+
+impl BoardLike for BitBoard {
+    fn get(&self, position: &Position) -> Option<&Piece> {
+        self.squares[position.index()].as_ref()
+    }
+
+    fn has_check(&self, position: &Position, player_color: &PieceColor) -> bool {
+        let enemy = !*player_color;
+        let bit = square_bit(position);
+
+        // An enemy pawn attacks the square from one of the two squares diagonally in front of it
+        let pawn_sources = match player_color {
+            PieceColor::White => north_east(bit) | north_west(bit),
+            PieceColor::Black => south_east(bit) | south_west(bit),
+        };
+
+        let diagonal_attackers = self.pieces(PieceType::Bishup, &enemy) | self.pieces(PieceType::Queen, &enemy);
+        let straight_attackers = self.pieces(PieceType::Rook, &enemy) | self.pieces(PieceType::Queen, &enemy);
+
+        knight_attacks(bit) & self.pieces(PieceType::Knight, &enemy) != 0
+            || king_attacks(bit) & self.pieces(PieceType::King, &enemy) != 0
+            || pawn_sources & self.pieces(PieceType::Pawn, &enemy) != 0
+            || self.bishup_attacks(position) & diagonal_attackers != 0
+            || self.rook_attacks(position) & straight_attackers != 0
+    }
+
+    fn get_knight_move_positions(&self, position: &Position, player_color: &PieceColor, get_captures_only: bool) -> Vec<Position> {
+        self.move_targets(knight_attacks(square_bit(position)), player_color, get_captures_only)
+    }
+
+    fn get_rook_move_positions(&self, position: &Position, player_color: &PieceColor, get_captures_only: bool) -> Vec<Position> {
+        self.move_targets(self.rook_attacks(position), player_color, get_captures_only)
+    }
+
+    fn get_bishup_move_positions(&self, position: &Position, player_color: &PieceColor, get_captures_only: bool) -> Vec<Position> {
+        self.move_targets(self.bishup_attacks(position), player_color, get_captures_only)
+    }
+}
+
+fn square_bit(position: &Position) -> u64 {
+    1 << position.index()
+}
+
+/// Gets the positions of the set bits, lowest first
+fn bit_positions(mut bits: u64) -> impl Iterator<Item = Position> {
+    std::iter::from_fn(move || {
+        if bits == 0 {
+            return None;
+        }
+
+        let index = bits.trailing_zeros() as usize;
+        bits &= bits - 1;
+        Position::from_index(index)
+    })
+}
+
+// Each shift moves every bit one square, dropping bits that would wrap around to the other side
+fn north(bits: u64) -> u64 {
+    bits << 8
+}
+
+fn south(bits: u64) -> u64 {
+    bits >> 8
+}
+
+fn east(bits: u64) -> u64 {
+    (bits << 1) & !FILE_A
+}
+
+fn west(bits: u64) -> u64 {
+    (bits >> 1) & !FILE_H
+}
+
+fn north_east(bits: u64) -> u64 {
+    (bits << 9) & !FILE_A
+}
+
+fn north_west(bits: u64) -> u64 {
+    (bits << 7) & !FILE_H
+}
+
+fn south_east(bits: u64) -> u64 {
+    (bits >> 7) & !FILE_A
+}
+
+fn south_west(bits: u64) -> u64 {
+    (bits >> 9) & !FILE_H
+}
+
+/// Squares reached by repeatedly shifting `from` until leaving the board or hitting a
+/// piece, including the square of the piece hit
+fn slide(from: u64, shift: fn(u64) -> u64, empty: u64) -> u64 {
+    let mut attacks = 0;
+    let mut ray = shift(from);
+
+    while ray != 0 {
+        attacks |= ray;
+        ray = shift(ray & empty);
+    }
+
+    attacks
+}
+
+fn knight_attacks(bits: u64) -> u64 {
+    let one_file = ((bits << 1) & !FILE_A) | ((bits >> 1) & !FILE_H);
+    let two_files = ((bits << 2) & !(FILE_A | FILE_B)) | ((bits >> 2) & !(FILE_G | FILE_H));
+
+    (one_file << 16) | (one_file >> 16) | (two_files << 8) | (two_files >> 8)
+}
+
+fn king_attacks(bits: u64) -> u64 {
+    let row = bits | east(bits) | west(bits);
+    (row | north(row) | south(row)) & !bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+
+    /// Everything BoardLike answers about a board, square by square
+    fn describe<B: BoardLike>(board: &B) -> Vec<String> {
+        let mut description = vec!();
+
+        for row in 0usize..=7usize {
+            for column in 0usize..=7usize {
+                let position = Position::encode(row, column);
+                let checks = [PieceColor::White, PieceColor::Black].map(|color| board.has_check(&position, &color));
+                description.push(format!("{} {:?} {:?}", position, board.get(&position), checks));
+
+                if let Some(piece) = board.get(&position) {
+                    for get_captures_only in [false, true] {
+                        for mut targets in [
+                            board.get_knight_move_positions(&position, &piece.color, get_captures_only),
+                            board.get_rook_move_positions(&position, &piece.color, get_captures_only),
+                            board.get_bishup_move_positions(&position, &piece.color, get_captures_only),
+                        ] {
+                            targets.sort_by_key(Position::index);
+                            description.push(format!("{} {:?}", position, targets));
+                        }
+                    }
+                }
+            }
+        }
+
+        description
+    }
+
+    /// Compares the two boards at every position of a perft tree, returning the leaf count
+    fn assert_parity(game: &Game, depth: usize) -> u64 {
+        let bitboard = BitBoard::from_board(&game.board);
+        assert!(describe(&game.board) == describe(&bitboard), "{}", game.to_fen());
+        assert!(bitboard.get_king(&game.turn) == game.board.get_king(&game.turn));
+
+        if depth == 0 {
+            return 1;
+        }
+
+        game.get_moves().iter().map(|chess_move| assert_parity(&game.peek_move(chess_move), depth - 1)).sum()
+    }
+
+    #[test]
+    fn test_matches_board_through_perft() {
+        // Quiet, tactical and endgame positions whose leaf counts are well known
+        assert!(assert_parity(&Game::new(), 2) == 400);
+        assert!(assert_parity(&Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").expect(""), 2) == 2039);
+        assert!(assert_parity(&Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").expect(""), 3) == 2812);
+    }
+
+    // Compares every one of the 206,604 positions in the tree, which takes over a minute even
+    // optimized, so it only runs with cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn test_matches_board_through_perft_depth_4() {
+        assert!(assert_parity(&Game::new(), 4) == 197281);
+    }
+
+    #[test]
+    fn test_shifts_stay_on_the_board() {
+        let h_file = FILE_H;
+        assert!(east(h_file) == 0 && north_east(h_file) == 0 && south_east(h_file) == 0);
+        assert!(west(FILE_A) == 0 && north_west(FILE_A) == 0 && south_west(FILE_A) == 0);
+
+        let corner = square_bit(&Position::from_str("a1").expect(""));
+        assert!(knight_attacks(corner).count_ones() == 2 && king_attacks(corner).count_ones() == 3);
+
+        let center = square_bit(&Position::from_str("d4").expect(""));
+        assert!(knight_attacks(center).count_ones() == 8 && king_attacks(center).count_ones() == 8);
+    }
+
+    #[test]
+    fn test_add_and_remove_pieces() {
+        let mut bitboard = BitBoard::from_board(&Game::new().board);
+        assert!(bitboard.occupied().count_ones() == 32);
+        assert!(bitboard.pieces(PieceType::Pawn, &PieceColor::White) == 0xff00);
+
+        let e2 = Position::from_str("e2").expect("");
+        let e4 = Position::from_str("e4").expect("");
+        assert!(bitboard.make_move(&e2, &e4).is_none());
+        assert!(bitboard.get(&e2).is_none() && bitboard.get(&e4).is_some());
+        assert!(bitboard.occupancy(&PieceColor::White) & square_bit(&e4) != 0);
+
+        let captured = bitboard.add_piece(Piece{piece_type: PieceType::Queen, color: PieceColor::Black}, &e4);
+        assert!(captured == Some(Piece{piece_type: PieceType::Pawn, color: PieceColor::White}));
+        assert!(bitboard.occupancy(&PieceColor::White) & square_bit(&e4) == 0);
+        assert!(bitboard.pieces(PieceType::Queen, &PieceColor::Black).count_ones() == 2);
+        assert!(bitboard.has_check(&Position::from_str("e1").expect(""), &PieceColor::White));
+    }
+}
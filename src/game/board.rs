@@ -1,10 +1,40 @@
 use super::piece::*;
 use super::position::*;
-use std::cmp::{PartialEq, Eq};
+use std::cmp::{self, PartialEq, Eq};
+use std::fmt;
+
+/// How open a file is from one side's point of view
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileStatus {
+    /// No pawns of either color
+    Open,
+    /// Only the opponent's pawns
+    HalfOpen,
+    /// At least one of the side's own pawns
+    Closed,
+}
+
+/// What move generation asks of a board, so the square array and the bitboards in
+/// [`super::bitboard`] can be checked against each other and swapped later
+pub trait BoardLike {
+    fn get(&self, position: &Position) -> Option<&Piece>;
+
+    /// Whether the enemies of `player_color` attack `position`
+    fn has_check(&self, position: &Position, player_color: &PieceColor) -> bool;
+
+    /// Squares a knight of `player_color` on `position` could move to, or only its captures
+    fn get_knight_move_positions(&self, position: &Position, player_color: &PieceColor, get_captures_only: bool) -> Vec<Position>;
+
+    fn get_rook_move_positions(&self, position: &Position, player_color: &PieceColor, get_captures_only: bool) -> Vec<Position>;
+
+    fn get_bishup_move_positions(&self, position: &Position, player_color: &PieceColor, get_captures_only: bool) -> Vec<Position>;
+}
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Board {
-    grid: [[Option<Piece>; 8]; 8]
+    grid: [[Option<Piece>; 8]; 8],
+    // Where each color's king is, by PieceColor, kept up to date by every change to the grid
+    kings: [Option<Position>; 2],
 }
 
 impl Board {
@@ -16,12 +46,16 @@ impl Board {
 
     pub fn add_piece(&mut self, piece: Piece, position: &Position) -> Option<Piece> {
         let (row, column) = position.decode();
-        self.grid[row][column].replace(piece)
+        let replaced = self.grid[row][column].replace(piece);
+        self.update_kings(replaced.as_ref(), Some(&piece), position);
+        replaced
     }
 
     pub fn remove_piece(&mut self, position: &Position) -> Option<Piece> {
         let (row, column) = position.decode();
-        self.grid[row][column].take()
+        let removed = self.grid[row][column].take();
+        self.update_kings(removed.as_ref(), None, position);
+        removed
     }
 
     pub fn get_pieces(&self, player_color: &PieceColor) -> Vec<(Position, PieceType)> {
@@ -41,6 +75,24 @@ impl Board {
     }
 
     pub fn get_king(&self, player_color: &PieceColor) -> Option<Position> {
+        self.kings[*player_color as usize]
+    }
+
+    /// Keeps the cached king positions right after `removed` leaves `position` and `added` takes its place
+    fn update_kings(&mut self, removed: Option<&Piece>, added: Option<&Piece>, position: &Position) {
+        if let Some(&Piece{piece_type: PieceType::King, color}) = removed {
+            if self.kings[color as usize] == Some(*position) {
+                // Positions set up with extra kings still have one to fall back on
+                self.kings[color as usize] = self.find_king(&color);
+            }
+        }
+
+        if let Some(&Piece{piece_type: PieceType::King, color}) = added {
+            self.kings[color as usize] = Some(*position);
+        }
+    }
+
+    fn find_king(&self, player_color: &PieceColor) -> Option<Position> {
         for row in 0usize..=7usize {
             for column in 0usize..=7usize {
                 if let Some(piece) = self.get(&Position::encode(row, column)) {
@@ -54,11 +106,57 @@ impl Board {
         None
     }
 
-    pub fn make_move(&mut self, from: &Position, to: &Position) -> Option<Piece> {
-        let (from_row, from_column) = from.decode();
-        let (to_row, to_column) = to.decode();
+    /// Classifies a file by the pawns on it
+    ///
+    /// # Arguments
+    ///
+    /// * `column` - The file, 0 for a through 7 for h
+    /// * `player_color` - The side whose point of view is used
+    pub fn file_status(&self, column: usize, player_color: &PieceColor) -> FileStatus {
+        let mut has_enemy_pawn = false;
 
-        self.grid[from_row][from_column].take().and_then(|piece| self.grid[to_row][to_column].replace(piece))
+        for row in 0usize..=7usize {
+            if let Some(&Piece{piece_type: PieceType::Pawn, color}) = self.get(&Position::encode(row, column)) {
+                if color == *player_color {
+                    return FileStatus::Closed;
+                }
+                has_enemy_pawn = true;
+            }
+        }
+
+        if has_enemy_pawn { FileStatus::HalfOpen } else { FileStatus::Open }
+    }
+
+    /// Whether a pawn has no enemy pawns ahead of it on its own or adjacent files
+    pub fn is_passed_pawn(&self, position: &Position, player_color: &PieceColor) -> bool {
+        let (row, column) = position.decode();
+        let rows_ahead = match player_color {
+            PieceColor::White => (row + 1)..8,
+            PieceColor::Black => 0..row,
+        };
+
+        for search_row in rows_ahead {
+            for search_column in column.saturating_sub(1)..=cmp::min(column + 1, 7) {
+                if self.get(&Position::encode(search_row, search_column)).is_some_and(|&Piece{piece_type, color}| piece_type == PieceType::Pawn && color != *player_color) {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Gets the positions of every passed pawn the side has
+    pub fn passed_pawns(&self, player_color: &PieceColor) -> Vec<Position> {
+        self.get_pieces(player_color).into_iter()
+            .filter(|(position, piece_type)| *piece_type == PieceType::Pawn && self.is_passed_pawn(position, player_color))
+            .map(|(position, _)| position)
+            .collect()
+    }
+
+    pub fn make_move(&mut self, from: &Position, to: &Position) -> Option<Piece> {
+        let piece = self.remove_piece(from)?;
+        self.add_piece(piece, to)
     }
 
     pub fn test_move(&self, from: &Position, to: &Position, king_position: &Position, player_color: &PieceColor) -> bool {
@@ -67,11 +165,19 @@ impl Board {
         !next_board.has_check(king_position, player_color)
     }
 
+    /// Same as test_move but also removes the pawn captured en passant
+    pub fn test_en_passant_move(&self, from: &Position, to: &Position, king_position: &Position, player_color: &PieceColor) -> bool {
+        let mut next_board = *self;
+        next_board.make_move(from, to);
+        next_board.remove_piece(&to.backward(player_color));
+        !next_board.has_check(king_position, player_color)
+    }
+
     // TODO: Edit to exit even faster
     pub fn has_check(&self, position: &Position, player_color: &PieceColor) -> bool {
         // Check Knight Moves
         for threat_position in self.get_knight_move_positions(position, player_color, true) {
-            if self.get(&threat_position).map_or(false, |&Piece{piece_type, color}| color != *player_color && piece_type == PieceType::Knight) {
+            if self.get(&threat_position).is_some_and(|&Piece{piece_type, color}| color != *player_color && piece_type == PieceType::Knight) {
                 return true;
             }
         }
@@ -83,10 +189,10 @@ impl Board {
             let threat_row = threat_position.row();
 
 
-            if self.get(&threat_position).map_or(false, |&Piece{piece_type, color}| color != *player_color && (
+            if self.get(&threat_position).is_some_and(|&Piece{piece_type, color}| color != *player_color && (
                     piece_type == PieceType::Queen ||
                     piece_type == PieceType::Bishup ||
-                    (piece_type == PieceType::Pawn && forward_position.map_or(false, |forward| threat_row == forward.row())) ||
+                    (piece_type == PieceType::Pawn && forward_position.is_some_and(|forward| threat_row == forward.row())) ||
                     (piece_type == PieceType::King && position.row().abs_diff(threat_row) == 1usize))) {
                 return true;
             }
@@ -94,7 +200,7 @@ impl Board {
 
         // Check Columns and Rows
         for threat_position in self.get_rook_move_positions(position, player_color, true) {
-            if self.get(&threat_position).map_or(false, |&Piece{piece_type, color}| color != *player_color && (
+            if self.get(&threat_position).is_some_and(|&Piece{piece_type, color}| color != *player_color && (
                 piece_type == PieceType::Queen || 
                 piece_type == PieceType::Rook ||
                 (piece_type == PieceType::King && (position.row().abs_diff(threat_position.row()) == 1usize || position.column().abs_diff(threat_position.column()) == 1usize)))) {
@@ -105,12 +211,126 @@ impl Board {
         false
     }
 
+    /// Gets the positions of all enemy pieces attacking a square, following the same rules as has_check
+    pub fn get_attackers(&self, position: &Position, player_color: &PieceColor) -> Vec<Position> {
+        let mut attackers = vec!();
+
+        for threat_position in self.get_knight_move_positions(position, player_color, true) {
+            if self.get(&threat_position).is_some_and(|&Piece{piece_type, color}| color != *player_color && piece_type == PieceType::Knight) {
+                attackers.push(threat_position);
+            }
+        }
+
+        let forward_position = position.forward_checked(player_color);
+
+        for threat_position in self.get_bishup_move_positions(position, player_color, true) {
+            let threat_row = threat_position.row();
+
+            if self.get(&threat_position).is_some_and(|&Piece{piece_type, color}| color != *player_color && (
+                    piece_type == PieceType::Queen ||
+                    piece_type == PieceType::Bishup ||
+                    (piece_type == PieceType::Pawn && forward_position.is_some_and(|forward| threat_row == forward.row())) ||
+                    (piece_type == PieceType::King && position.row().abs_diff(threat_row) == 1usize))) {
+                attackers.push(threat_position);
+            }
+        }
+
+        for threat_position in self.get_rook_move_positions(position, player_color, true) {
+            if self.get(&threat_position).is_some_and(|&Piece{piece_type, color}| color != *player_color && (
+                piece_type == PieceType::Queen ||
+                piece_type == PieceType::Rook ||
+                (piece_type == PieceType::King && (position.row().abs_diff(threat_position.row()) == 1usize || position.column().abs_diff(threat_position.column()) == 1usize)))) {
+                attackers.push(threat_position);
+            }
+        }
+
+        attackers
+    }
+
+    /// Marks every square attacked by the side's pieces in one pass over the board,
+    /// indexed by row then column. Squares holding the side's own pieces count as
+    /// attacked when they are defended
+    pub fn attack_map(&self, player_color: &PieceColor) -> [[bool; 8]; 8] {
+        let mut attacked = [[false; 8]; 8];
+        self.for_each_attack(player_color, |_, target| attacked[target.row()][target.column()] = true);
+        attacked
+    }
+
+    /// Calls `f` with every (attacker, attacked square) pair for the side's pieces,
+    /// so a square attacked by several pieces is visited once per attacker
+    pub fn for_each_attack<F: FnMut(Position, Position)>(&self, player_color: &PieceColor, mut f: F) {
+        for (position, piece_type) in self.get_pieces(player_color) {
+            let (steps, is_sliding): (&[(isize, isize)], bool) = match piece_type {
+                PieceType::Knight => (&[(-1,-2),(-1,2),(1,-2),(1,2),(-2,-1),(-2,1),(2,-1),(2,1)], false),
+                PieceType::Bishup => (&[(-1,-1),(-1,1),(1,-1),(1,1)], true),
+                PieceType::Rook => (&[(-1,0),(1,0),(0,-1),(0,1)], true),
+                PieceType::Queen => (&[(-1,-1),(-1,0),(-1,1),(0,-1),(0,1),(1,-1),(1,0),(1,1)], true),
+                PieceType::King => (&[(-1,-1),(-1,0),(-1,1),(0,-1),(0,1),(1,-1),(1,0),(1,1)], false),
+                PieceType::Pawn => {
+                    if let Some(forward) = position.forward_checked(player_color) {
+                        for column_step in [-1, 1] {
+                            if let Some(target) = forward.offset(0, column_step) {
+                                f(position, target);
+                            }
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            for &(row_step, column_step) in steps {
+                let mut search = position.offset(row_step, column_step);
+
+                while let Some(target) = search {
+                    f(position, target);
+
+                    if !is_sliding || self.get(&target).is_some() {
+                        break;
+                    }
+                    search = target.offset(row_step, column_step);
+                }
+            }
+        }
+    }
+
+    /// Gets each of the side's pieces pinned to its king along with the direction
+    /// of the pin ray, as a (row, column) step outward from the king
+    pub fn pinned_pieces(&self, king: &Position, player_color: &PieceColor) -> Vec<(Position, (isize, isize))> {
+        let mut pins = vec!();
+
+        for increments in [(-1,-1),(-1,0),(-1,1),(0,-1),(0,1),(1,-1),(1,0),(1,1)] {
+            let is_diagonal = increments.0 != 0 && increments.1 != 0;
+            let mut blocker: Option<Position> = None;
+            let mut search = king.offset(increments.0, increments.1);
+
+            while let Some(search_position) = search {
+                if let Some(&Piece{piece_type, color}) = self.get(&search_position) {
+                    if color == *player_color {
+                        if blocker.is_some() {
+                            break;
+                        }
+                        blocker = Some(search_position);
+                    }
+                    else {
+                        let is_pinning = piece_type == PieceType::Queen || (is_diagonal && piece_type == PieceType::Bishup) || (!is_diagonal && piece_type == PieceType::Rook);
+                        if let Some(pinned) = blocker.filter(|_| is_pinning) {
+                            pins.push((pinned, increments));
+                        }
+                        break;
+                    }
+                }
+                search = search_position.offset(increments.0, increments.1);
+            }
+        }
+
+        pins
+    }
+
     pub fn get_knight_move_positions(&self, position: &Position, player_color: &PieceColor, get_captures_only: bool) -> Vec<Position> {
-        let (row, column) = position.decode_isize();
         let mut knight_positions = vec!();
 
         for (row_increment, column_increment) in [(-1,-2),(-1,2),(1,-2),(1,2),(-2,-1),(-2,1),(2,-1),(2,1)] {
-            if let Some(knight_pos) = Position::encode_checked(row + row_increment, column + column_increment) {
+            if let Some(knight_pos) = position.offset(row_increment, column_increment) {
                 if self.get(&knight_pos).map_or(!get_captures_only, |&Piece{piece_type:_, color}| color != *player_color){
                     knight_positions.push(knight_pos);
                 }
@@ -141,10 +361,9 @@ impl Board {
     }
 
     fn add_positions_in_direction(&self, position: &Position, increments: (isize, isize), player_color: &PieceColor, get_captures_only: bool, moves: &mut Vec<Position>) {
-        let (row, column) = position.decode_isize();
-        let (mut search_row, mut search_column) = (row + increments.0, column + increments.1);
+        let mut search = position.offset(increments.0, increments.1);
 
-        while let Some(search_position) = Position::encode_checked(search_row, search_column) {
+        while let Some(search_position) = search {
             if let Some(piece) = self.get(&search_position) {
                 if piece.color != *player_color {
                     moves.push(search_position);
@@ -154,25 +373,34 @@ impl Board {
             if !get_captures_only {
                 moves.push(search_position);
             }
-            search_row += increments.0;
-            search_column += increments.1;
+            search = search_position.offset(increments.0, increments.1);
         }
     }
 
     pub fn default() -> Board {
         Board {
-            grid: Default::default()
+            grid: Default::default(),
+            kings: [None; 2],
         }
     }
 
     pub fn print(&self) {
+        self.print_with(|piece| piece.to_char());
+    }
+
+    /// Prints the board like print but with chess glyphs instead of letters
+    pub fn print_unicode(&self) {
+        self.print_with(Piece::to_unicode);
+    }
+
+    fn print_with<F: Fn(&Piece) -> char>(&self, piece_char: F) {
         use colored::*;
         let mut toggle = false;
         for (row, index) in self.grid.iter().rev().zip((1..=8).rev()) {
             print!("{} ", index);
             for square in row.iter() {
                 let value = format!(" {} ", match square {
-                    Some(p) => p.to_char(),
+                    Some(p) => piece_char(p),
                     None => ' ',
                 }).normal();
 
@@ -191,3 +419,168 @@ impl Board {
         println!("   a  b  c  d  e  f  g  h ");
     }
 }
+
+/// The same layout as print without the colored squares
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (row, index) in self.grid.iter().rev().zip((1..=8).rev()) {
+            write!(f, "{} ", index)?;
+            for square in row.iter() {
+                write!(f, " {} ", match square {
+                    Some(p) => p.to_char(),
+                    None => ' ',
+                })?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "   a  b  c  d  e  f  g  h ")
+    }
+}
+
+impl BoardLike for Board {
+    fn get(&self, position: &Position) -> Option<&Piece> {
+        Board::get(self, position)
+    }
+
+    fn has_check(&self, position: &Position, player_color: &PieceColor) -> bool {
+        Board::has_check(self, position, player_color)
+    }
+
+    fn get_knight_move_positions(&self, position: &Position, player_color: &PieceColor, get_captures_only: bool) -> Vec<Position> {
+        Board::get_knight_move_positions(self, position, player_color, get_captures_only)
+    }
+
+    fn get_rook_move_positions(&self, position: &Position, player_color: &PieceColor, get_captures_only: bool) -> Vec<Position> {
+        Board::get_rook_move_positions(self, position, player_color, get_captures_only)
+    }
+
+    fn get_bishup_move_positions(&self, position: &Position, player_color: &PieceColor, get_captures_only: bool) -> Vec<Position> {
+        Board::get_bishup_move_positions(self, position, player_color, get_captures_only)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+
+    #[test]
+    fn test_display() {
+        let expected = "\
+8  r  n  b  q  k  b  n  r \n\
+7  p  p  p  p  p  p  p  p \n\
+6                         \n\
+5                         \n\
+4                         \n\
+3                         \n\
+2  P  P  P  P  P  P  P  P \n\
+1  R  N  B  Q  K  B  N  R \n\
+\x20  a  b  c  d  e  f  g  h \n";
+        assert_eq!(Game::new().board.to_string(), expected);
+    }
+
+    #[test]
+    fn test_get_king() {
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").expect("");
+        assert!(game.board.get_king(&PieceColor::White) == Some(Position::from_str("e1").expect("")));
+
+        // Castling moves the king with the rook, then each king walks a square
+        game.play_line("O-O O-O-O g1g2 c8b7").expect("");
+        assert!(game.board.get_king(&PieceColor::White) == Some(Position::from_str("g2").expect("")));
+        assert!(game.board.get_king(&PieceColor::Black) == Some(Position::from_str("b7").expect("")));
+
+        // Taken away and put back by hand
+        let mut board = game.board;
+        let king = board.remove_piece(&Position::from_str("g2").expect("")).expect("");
+        assert!(board.get_king(&PieceColor::White).is_none());
+        board.add_piece(king, &Position::from_str("d4").expect(""));
+        assert!(board.get_king(&PieceColor::White) == Some(Position::from_str("d4").expect("")));
+
+        // A king captured on the board no longer counts
+        board.make_move(&Position::from_str("b7").expect(""), &Position::from_str("d4").expect(""));
+        assert!(board.get_king(&PieceColor::White).is_none());
+        assert!(board.get_king(&PieceColor::Black) == Some(Position::from_str("d4").expect("")));
+    }
+
+    #[test]
+    fn test_pinned_pieces() {
+        let game = Game::from_fen("4k3/8/8/8/1b6/8/3N4/4K3 w - - 0 1").expect("");
+        let pins = game.board.pinned_pieces(&Position::from_str("e1").expect(""), &PieceColor::White);
+        assert!(pins == vec![(Position::from_str("d2").expect(""), (1, -1))]);
+
+        // A second piece on the ray means neither is pinned
+        let game = Game::from_fen("4k3/8/8/8/1b6/2P5/3N4/4K3 w - - 0 1").expect("");
+        assert!(game.board.pinned_pieces(&Position::from_str("e1").expect(""), &PieceColor::White).is_empty());
+
+        // A rook can't pin along a diagonal
+        let game = Game::from_fen("4k3/8/8/8/1r6/8/3N4/4K3 w - - 0 1").expect("");
+        assert!(game.board.pinned_pieces(&Position::from_str("e1").expect(""), &PieceColor::White).is_empty());
+    }
+
+    #[test]
+    fn test_attack_map() {
+        let game = Game::from_fen("4k3/8/8/3p4/8/5N2/1P6/R3K3 w - - 0 1").expect("");
+        let is_attacked = |attacked: &[[bool; 8]; 8], square: &str| {
+            let position = Position::from_str(square).expect("");
+            attacked[position.row()][position.column()]
+        };
+
+        let white = game.board.attack_map(&PieceColor::White);
+        // Pawn captures but not pushes
+        assert!(is_attacked(&white, "a3") && is_attacked(&white, "c3"));
+        assert!(!is_attacked(&white, "b3"));
+        // The rook's file is blocked by nothing, its rank stops at the king
+        assert!(is_attacked(&white, "a8") && is_attacked(&white, "d1"));
+        assert!(!is_attacked(&white, "h1"));
+        // Defended pieces and enemy pieces are attacked
+        assert!(is_attacked(&white, "e1") && is_attacked(&white, "d2"));
+        assert!(is_attacked(&white, "d4") && !is_attacked(&white, "d5"));
+
+        let black = game.board.attack_map(&PieceColor::Black);
+        assert!(is_attacked(&black, "c4") && is_attacked(&black, "e4"));
+        assert!(!is_attacked(&black, "d4"));
+        assert!(is_attacked(&black, "d7") && !is_attacked(&black, "e6"));
+    }
+
+    #[test]
+    fn test_for_each_attack() {
+        let game = Game::from_fen("4k3/8/8/8/3Q4/8/8/4K3 w - - 0 1").expect("");
+        let queen = Position::from_str("d4").expect("");
+        let mut queen_attacks = vec!();
+
+        game.board.for_each_attack(&PieceColor::White, |attacker, attacked| {
+            if attacker == queen {
+                queen_attacks.push(attacked);
+            }
+        });
+
+        assert!(queen_attacks.len() == 27);
+        assert!(queen_attacks.contains(&Position::from_str("a7").expect("")));
+        assert!(!queen_attacks.contains(&Position::from_str("e6").expect("")));
+    }
+
+    #[test]
+    fn test_file_status_and_passed_pawns() {
+        let game = Game::from_fen("4k3/2p5/8/1P6/8/8/5P2/4K3 w - - 0 1").expect("");
+
+        assert!(game.board.file_status(0, &PieceColor::White) == FileStatus::Open);
+        assert!(game.board.file_status(2, &PieceColor::White) == FileStatus::HalfOpen);
+        assert!(game.board.file_status(2, &PieceColor::Black) == FileStatus::Closed);
+
+        assert!(!game.board.is_passed_pawn(&Position::from_str("b5").expect(""), &PieceColor::White));
+        assert!(game.board.is_passed_pawn(&Position::from_str("f2").expect(""), &PieceColor::White));
+        assert!(!game.board.is_passed_pawn(&Position::from_str("c7").expect(""), &PieceColor::Black));
+    }
+
+    #[test]
+    fn test_passed_pawns() {
+        // The a-pawns block each other, while black's h3 pawn has already gone past
+        // white's g4 pawn so both of those are passed
+        let game = Game::from_fen("4k3/8/p7/3P4/P5P1/7p/8/4K3 w - - 0 1").expect("");
+
+        assert!(game.board.passed_pawns(&PieceColor::White) == vec![Position::from_str("g4").expect(""), Position::from_str("d5").expect("")]);
+        assert!(game.board.passed_pawns(&PieceColor::Black) == vec![Position::from_str("h3").expect("")]);
+        assert!(!game.board.is_passed_pawn(&Position::from_str("a4").expect(""), &PieceColor::White));
+        assert!(!game.board.is_passed_pawn(&Position::from_str("a6").expect(""), &PieceColor::Black));
+    }
+}
@@ -1,9 +1,13 @@
-use super::{piece::PieceType, position::Position};
+use super::{board::Board, piece::{Piece, PieceColor, PieceType}, position::Position, Game};
 use std::fmt;
 use regex::*;
 use lazy_static::*;
+use eyre::{eyre, Report, Result};
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+/// Moves are ordered by variant, then from square, to square and promotion type, which gives
+/// the engine a fixed way to break ties between equally scored moves
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ChessMove {
     CastleKingside,
     CastleQueenside,
@@ -12,7 +16,6 @@ pub enum ChessMove {
 }
 
 impl ChessMove {
-    #[allow(dead_code)]
     pub fn from_str(move_str: &str) -> Option<ChessMove> {
         lazy_static! {
             static ref MOVE_REGEX: Regex = Regex::new(r"(?P<from>[a-h][1-8])(?P<to>[a-h][1-8])").unwrap();
@@ -45,6 +48,135 @@ impl ChessMove {
             }
         }
     }
+
+    pub fn is_castle(&self) -> bool {
+        matches!(self, ChessMove::CastleKingside | ChessMove::CastleQueenside)
+    }
+
+    /// Gets the piece type a pawn promotes to, or None for any other move
+    pub fn is_promotion(&self) -> Option<PieceType> {
+        match self {
+            ChessMove::PawnPromote(_, _, piece_type) => Some(*piece_type),
+            _ => None,
+        }
+    }
+
+    /// Whether the move takes a piece on the board it is played from, including en passant,
+    /// which is the only way a pawn can move diagonally to an empty square
+    pub fn is_capture(&self, board: &Board) -> bool {
+        match self {
+            ChessMove::CastleKingside | ChessMove::CastleQueenside => false,
+            ChessMove::Move(from, to) | ChessMove::PawnPromote(from, to, _) => {
+                board.get(to).is_some() || (from.column() != to.column() && board.get(from).is_some_and(|piece| piece.piece_type == PieceType::Pawn))
+            }
+        }
+    }
+
+    /// Gets the piece the move picks up, which for castling is the king of `player_color`
+    pub fn moved_piece(&self, board: &Board, player_color: &PieceColor) -> Option<Piece> {
+        match self {
+            ChessMove::CastleKingside | ChessMove::CastleQueenside => Some(Piece{piece_type: PieceType::King, color: *player_color}),
+            ChessMove::Move(from, _) | ChessMove::PawnPromote(from, _, _) => board.get(from).copied(),
+        }
+    }
+
+    /// Formats the move in the coordinate notation UCI uses, where castling is written as
+    /// the king's two-square move
+    pub fn to_uci(&self, player_color: &PieceColor) -> String {
+        let back_rank = match player_color {
+            PieceColor::White => 1,
+            PieceColor::Black => 8,
+        };
+
+        match self {
+            ChessMove::CastleKingside => format!("e{}g{}", back_rank, back_rank),
+            ChessMove::CastleQueenside => format!("e{}c{}", back_rank, back_rank),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Formats the move in standard algebraic notation for the position it is played from
+    ///
+    /// # Arguments
+    ///
+    /// * `game` - The game in which this move is legal, before the move is made
+    pub fn to_san(&self, game: &Game) -> String {
+        let mut san = match self {
+            ChessMove::CastleKingside => "O-O".to_string(),
+            ChessMove::CastleQueenside => "O-O-O".to_string(),
+            ChessMove::Move(from, to) | ChessMove::PawnPromote(from, to, _) => {
+                let piece_type = self.moved_piece(&game.board, &game.turn).map_or(PieceType::Pawn, |piece| piece.piece_type);
+                let is_capture = self.is_capture(&game.board);
+                let mut san = String::new();
+
+                if piece_type == PieceType::Pawn {
+                    if is_capture {
+                        san.push_str(&from.to_string()[..1]);
+                    }
+                }
+                else {
+                    san.push(piece_type.to_char().to_ascii_uppercase());
+                    san.push_str(&ChessMove::disambiguation(game, from, to, piece_type));
+                }
+
+                if is_capture {
+                    san.push('x');
+                }
+
+                san.push_str(&to.to_string());
+
+                if let Some(promote_type) = self.is_promotion() {
+                    san.push('=');
+                    san.push(promote_type.to_char().to_ascii_uppercase());
+                }
+
+                san
+            }
+        };
+
+        let next_game = game.peek_move(self);
+
+        if next_game.is_checkmate() {
+            san.push('#');
+        }
+        else if next_game.is_in_check() {
+            san.push('+');
+        }
+
+        san
+    }
+
+    /// Gets the file, rank or square needed to tell this move apart from other pieces of
+    /// the same type which can also reach the destination
+    fn disambiguation(game: &Game, from: &Position, to: &Position, piece_type: PieceType) -> String {
+        let rivals: Vec<Position> = game.moves_for_piece_type(piece_type).into_iter().filter_map(|chess_move| match chess_move {
+            ChessMove::Move(other_from, other_to) if other_to == *to && other_from != *from => Some(other_from),
+            _ => None,
+        }).collect();
+
+        let square = from.to_string();
+
+        if rivals.is_empty() {
+            String::new()
+        }
+        else if rivals.iter().all(|rival| rival.column() != from.column()) {
+            square[..1].to_string()
+        }
+        else if rivals.iter().all(|rival| rival.row() != from.row()) {
+            square[1..].to_string()
+        }
+        else {
+            square
+        }
+    }
+}
+
+impl TryFrom<&str> for ChessMove {
+    type Error = Report;
+
+    fn try_from(move_str: &str) -> Result<ChessMove> {
+        ChessMove::from_str(move_str).ok_or_else(|| eyre!("Could not parse move \"{}\"", move_str))
+    }
 }
 
 impl fmt::Display for ChessMove {
@@ -56,4 +188,72 @@ impl fmt::Display for ChessMove {
             ChessMove::PawnPromote(from, to, piece_type) => write!(f, "{}{}{}", from, to, piece_type),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_after(moves: &[&str]) -> Game {
+        let mut game = Game::new();
+        for move_str in moves {
+            game.make_move(&ChessMove::from_str(move_str).expect(""));
+        }
+        game
+    }
+
+    #[test]
+    fn test_castle() {
+        let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").expect("");
+
+        for castle in [ChessMove::CastleKingside, ChessMove::CastleQueenside] {
+            assert!(castle.is_castle());
+            assert!(castle.is_promotion().is_none());
+            assert!(!castle.is_capture(&game.board));
+            assert!(castle.moved_piece(&game.board, &PieceColor::Black) == Some(Piece{piece_type: PieceType::King, color: PieceColor::Black}));
+        }
+    }
+
+    #[test]
+    fn test_promotion() {
+        let game = Game::from_fen("1r5k/P7/8/8/8/8/8/K7 w - - 0 1").expect("");
+
+        let push = ChessMove::from_str("a7a8q").expect("");
+        assert!(push.is_promotion() == Some(PieceType::Queen));
+        assert!(!push.is_castle() && !push.is_capture(&game.board));
+
+        let capture = ChessMove::from_str("a7b8n").expect("");
+        assert!(capture.is_promotion() == Some(PieceType::Knight));
+        assert!(capture.is_capture(&game.board));
+        assert!(capture.moved_piece(&game.board, &game.turn) == Some(Piece{piece_type: PieceType::Pawn, color: PieceColor::White}));
+    }
+
+    #[test]
+    fn test_en_passant_capture() {
+        let game = game_after(&["e2e4", "a7a6", "e4e5", "d7d5"]);
+        let en_passant = ChessMove::from_str("e5d6").expect("");
+
+        assert!(game.get_moves().contains(&en_passant));
+        assert!(game.board.get(&Position::from_str("d6").expect("")).is_none());
+        assert!(en_passant.is_capture(&game.board));
+        assert!(!en_passant.is_castle() && en_passant.is_promotion().is_none());
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut moves: Vec<ChessMove> = ["e7e8q", "e2e4", "O-O-O", "e7e8n", "d2d4", "e2e3", "O-O", "a7a8q"].iter().map(|move_str| ChessMove::from_str(move_str).expect("")).collect();
+        moves.sort();
+
+        let sorted: Vec<String> = moves.iter().map(|chess_move| chess_move.to_string()).collect();
+        assert!(sorted == ["O-O", "O-O-O", "d2d4", "e2e3", "e2e4", "a7a8q", "e7e8n", "e7e8q"]);
+    }
+
+    #[test]
+    fn test_quiet_move() {
+        let game = Game::new();
+        let quiet = ChessMove::from_str("g1f3").expect("");
+
+        assert!(!quiet.is_capture(&game.board) && !quiet.is_castle() && quiet.is_promotion().is_none());
+        assert!(quiet.moved_piece(&game.board, &game.turn) == Some(Piece{piece_type: PieceType::Knight, color: PieceColor::White}));
+        assert!(ChessMove::from_str("e2e3").expect("").moved_piece(&game.board, &game.turn).is_some_and(|piece| piece.piece_type == PieceType::Pawn));
+    }
+}
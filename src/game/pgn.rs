@@ -0,0 +1,226 @@
+use super::{chess_move::ChessMove, piece::PieceColor, Game, GameResult, START_FEN};
+use std::collections::HashMap;
+use regex::*;
+use lazy_static::*;
+use eyre::{eyre, Result};
+
+/// The tags every PGN export is expected to carry, in their standard order
+pub const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// A game in Portable Game Notation split into its tag pairs and its movetext,
+/// so the metadata can be read without replaying any moves
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Pgn {
+    pub tags: HashMap<String, String>,
+    pub movetext: String,
+}
+
+impl Pgn {
+    pub fn from_str(pgn: &str) -> Result<Pgn> {
+        lazy_static! {
+            static ref TAG_REGEX: Regex = Regex::new(r#"^\[\s*(?P<name>[A-Za-z0-9_]+)\s+"(?P<value>(?:[^"\\]|\\.)*)"\s*\]$"#).unwrap();
+        }
+
+        let mut tags = HashMap::new();
+        let mut movetext = vec!();
+
+        for line in pgn.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if line.starts_with('[') && movetext.is_empty() {
+                let captures = TAG_REGEX.captures(line).ok_or_else(|| eyre!("Invalid PGN tag line: {}", line))?;
+                let value = captures["value"].replace("\\\"", "\"").replace("\\\\", "\\");
+                tags.insert(captures["name"].to_owned(), value);
+            }
+            else {
+                movetext.push(line);
+            }
+        }
+
+        Ok(Pgn {tags, movetext: movetext.join(" ")})
+    }
+
+    /// Gets a tag's value, such as tag("White") for the name of the player with the white pieces
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.get(name).map(String::as_str)
+    }
+
+    /// Whether all seven roster tags are present
+    pub fn has_seven_tag_roster(&self) -> bool {
+        SEVEN_TAG_ROSTER.iter().all(|name| self.tags.contains_key(*name))
+    }
+}
+
+/// Writes a game played from `start` as PGN, with placeholder roster tags, a FEN tag
+/// when the game didn't begin from the standard position and the result from `status()`
+pub fn to_pgn(moves: &[ChessMove], start: &Game) -> Result<String> {
+    let movetext = start.san_line(moves)?;
+
+    let mut game = start.clone();
+    for chess_move in moves {
+        game.make_move(chess_move);
+    }
+    let result = result_tag(game.status());
+
+    let mut pgn = String::new();
+    for (name, value) in SEVEN_TAG_ROSTER.iter().zip(["?", "?", "????.??.??", "?", "?", "?", result]) {
+        pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+    }
+
+    let fen = start.to_fen();
+    if fen != START_FEN {
+        pgn.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", fen));
+    }
+
+    if movetext.is_empty() {
+        pgn.push_str(&format!("\n{}\n", result));
+    }
+    else {
+        pgn.push_str(&format!("\n{} {}\n", movetext, result));
+    }
+
+    Ok(pgn)
+}
+
+/// Reads a PGN game, starting from its FEN tag if it has one, and plays through its
+/// movetext while skipping comments, variations, annotation glyphs and the result
+///
+/// Returns the starting position along with the moves played from it
+pub fn from_pgn(pgn: &str) -> Result<(Game, Vec<ChessMove>)> {
+    let pgn = Pgn::from_str(pgn)?;
+
+    let start = match pgn.tag("FEN") {
+        Some(fen) => Game::from_fen(fen)?,
+        None => Game::new(),
+    };
+
+    let mut line = String::new();
+    let mut comment_depth = 0;
+    let mut variation_depth = 0;
+
+    for c in pgn.movetext.chars() {
+        match c {
+            '{' => comment_depth += 1,
+            '}' if comment_depth > 0 => comment_depth -= 1,
+            '(' if comment_depth == 0 => variation_depth += 1,
+            ')' if comment_depth == 0 && variation_depth > 0 => variation_depth -= 1,
+            _ if comment_depth == 0 && variation_depth == 0 => line.push(c),
+            _ => (),
+        }
+    }
+
+    if comment_depth > 0 || variation_depth > 0 {
+        return Err(eyre!("Unclosed comment or variation in PGN movetext"));
+    }
+
+    let moves_only: Vec<&str> = line.split_whitespace()
+        .filter(|token| !token.starts_with('$') && !["1-0", "0-1", "1/2-1/2", "*"].contains(token))
+        .collect();
+
+    let mut game = start.clone();
+    let moves = game.play_line(&moves_only.join(" "))?;
+
+    Ok((start, moves))
+}
+
+/// Gets the Result tag for a game's status, "*" while it can still go on
+fn result_tag(result: GameResult) -> &'static str {
+    match result {
+        GameResult::Checkmate(PieceColor::Black) => "1-0",
+        GameResult::Checkmate(PieceColor::White) => "0-1",
+        GameResult::Stalemate | GameResult::SeventyFiveMove | GameResult::Fivefold | GameResult::InsufficientMaterial => "1/2-1/2",
+        GameResult::Ongoing | GameResult::FiftyMoveClaimable | GameResult::ThreefoldClaimable => "*",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"[Event "F/S Return Match"]
+[Site "Belgrade, Serbia JUG"]
+[Date "1992.11.04"]
+[Round "29"]
+[White "Fischer, Robert J."]
+[Black "Spassky, Boris V."]
+[Result "1/2-1/2"]
+[Annotator "\"Anon\""]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 {This opening is called the Ruy Lopez.} 3... a6
+4. Ba4 Nf6 1/2-1/2
+"#;
+
+    #[test]
+    fn test_from_str() {
+        let pgn = Pgn::from_str(HEADER).expect("");
+
+        assert!(pgn.has_seven_tag_roster());
+        assert!(pgn.tags.len() == 8);
+        assert!(pgn.tag("Site") == Some("Belgrade, Serbia JUG"));
+        assert!(pgn.tag("White") == Some("Fischer, Robert J."));
+        assert!(pgn.tag("Result") == Some("1/2-1/2"));
+        assert!(pgn.tag("Annotator") == Some("\"Anon\""));
+        assert!(pgn.tag("ECO").is_none());
+        assert!(pgn.movetext == "1. e4 e5 2. Nf3 Nc6 3. Bb5 {This opening is called the Ruy Lopez.} 3... a6 4. Ba4 Nf6 1/2-1/2");
+    }
+
+    #[test]
+    fn test_from_str_invalid_tag() {
+        assert!(Pgn::from_str("[Event Casual]\n\n1. e4").is_err());
+        assert!(Pgn::from_str("1. e4 e5").is_ok_and(|pgn| pgn.tags.is_empty() && !pgn.has_seven_tag_roster()));
+    }
+
+    #[test]
+    fn test_to_pgn() {
+        let mut game = Game::new();
+        let moves = game.play_line("f2f3 e7e5 g2g4 d8h4").expect("");
+
+        let pgn = to_pgn(&moves, &Game::new()).expect("");
+        assert!(pgn.ends_with("\n\n1. f3 e5 2. g4 Qh4# 0-1\n"));
+        assert!(!pgn.contains("[FEN"));
+
+        let parsed = Pgn::from_str(&pgn).expect("");
+        assert!(parsed.has_seven_tag_roster());
+        assert!(parsed.tag("Result") == Some("0-1"));
+
+        let start = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 30").expect("");
+        let pgn = to_pgn(&[ChessMove::from_str("a1a8").expect("")], &start).expect("");
+        let parsed = Pgn::from_str(&pgn).expect("");
+        assert!(parsed.tag("FEN") == Some("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 30"));
+        assert!(parsed.tag("Result") == Some("1-0"));
+        assert!(parsed.movetext == "30. Ra8# 1-0");
+
+        assert!(to_pgn(&[ChessMove::from_str("a1a7").expect("")], &Game::new()).is_err());
+    }
+
+    #[test]
+    fn test_from_pgn() {
+        let pgn = r#"[Event "Casual Game"]
+[Site "Berlin GER"]
+[Date "1852.??.??"]
+[Round "?"]
+[White "Adolf Anderssen"]
+[Black "Jean Dufresne"]
+[Result "*"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. b4 Bxb4 {The Evans Gambit} 5. c3 Ba5
+6. d4 exd4 (6... d6 7. Qb3) 7. O-O d3 $6 8. Qb3 Qf6 9. e5 Qg6 10. Re1 Nge7 *
+"#;
+
+        let (start, moves) = from_pgn(pgn).expect("");
+        assert!(start == Game::new());
+        assert!(moves.len() == 20);
+        assert!(moves[12] == ChessMove::CastleKingside);
+
+        let mut game = start.clone();
+        for chess_move in moves.iter() {
+            game.make_move(chess_move);
+        }
+        assert!(game.to_fen() == "r1b1k2r/ppppnppp/2n3q1/b3P3/2B5/1QPp1N2/P4PPP/RNB1R1K1 w kq - 3 11");
+
+        let (start, moves) = from_pgn("[FEN \"6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 30\"]\n\n30. Ra8# 1-0").expect("");
+        assert!(start.full_moves == 30);
+        assert!(moves == vec![ChessMove::from_str("a1a8").expect("")]);
+
+        assert!(from_pgn("1. e4 e5 2. Ke3").is_err_and(|error| error.to_string().contains("Ke3")));
+        assert!(from_pgn("1. e4 {unclosed").is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use std::ops::Not;
 use std::fmt;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Piece {
     pub piece_type: PieceType,
     pub color: PieceColor,
@@ -13,7 +13,7 @@ impl Piece {
         PieceType::from_char(name).map(|pt| Piece{piece_type: pt, color})
     }
 
-    pub fn to_char(&self) -> char {
+    pub fn to_char(self) -> char {
         use PieceColor::*;
 
         let result = self.piece_type.to_char();
@@ -27,12 +27,34 @@ impl Piece {
             }
         }
     }
+
+    /// Gets the chess glyph for the piece, outlined for white and filled for black
+    pub fn to_unicode(&self) -> char {
+        use PieceType::*;
+
+        match (&self.color, &self.piece_type) {
+            (PieceColor::White, King) => '\u{2654}',
+            (PieceColor::White, Queen) => '\u{2655}',
+            (PieceColor::White, Rook) => '\u{2656}',
+            (PieceColor::White, Bishup) => '\u{2657}',
+            (PieceColor::White, Knight) => '\u{2658}',
+            (PieceColor::White, Pawn) => '\u{2659}',
+            (PieceColor::Black, King) => '\u{265a}',
+            (PieceColor::Black, Queen) => '\u{265b}',
+            (PieceColor::Black, Rook) => '\u{265c}',
+            (PieceColor::Black, Bishup) => '\u{265d}',
+            (PieceColor::Black, Knight) => '\u{265e}',
+            (PieceColor::Black, Pawn) => '\u{265f}',
+        }
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PieceType {
     Pawn = 1,
     Knight = 2,
+    #[cfg_attr(feature = "serde", serde(rename = "Bishop"))]
     Bishup = 3,
     Rook = 4,
     Queen = 5,
@@ -75,6 +97,18 @@ impl PieceType {
             Pawn => 'p',
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        use PieceType::*;
+        match self {
+            King => "King",
+            Queen => "Queen",
+            Bishup => "Bishop",
+            Knight => "Knight",
+            Rook => "Rook",
+            Pawn => "Pawn",
+        }
+    }
 }
 
 impl fmt::Display for PieceType {
@@ -83,7 +117,14 @@ impl fmt::Display for PieceType {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+impl fmt::Debug for PieceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PieceColor {
     Black = 0,
     White = 1,
@@ -98,7 +139,7 @@ impl PieceColor {
         }
     }
 
-    pub fn to_char(&self) -> char {
+    pub fn to_char(self) -> char {
         match self {
             PieceColor::Black => 'b',
             PieceColor::White => 'w',
@@ -121,4 +162,28 @@ impl Not for PieceColor {
             PieceColor::White => PieceColor::Black,
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_piece_type_name() {
+        assert_eq!(PieceType::King.name(), "King");
+        assert_eq!(PieceType::Queen.name(), "Queen");
+        assert_eq!(PieceType::Bishup.name(), "Bishop");
+        assert_eq!(PieceType::Knight.name(), "Knight");
+        assert_eq!(PieceType::Rook.name(), "Rook");
+        assert_eq!(PieceType::Pawn.name(), "Pawn");
+        assert_eq!(format!("{:?}", PieceType::Bishup), "Bishop");
+    }
+
+    #[test]
+    fn test_to_unicode() {
+        assert_eq!(Piece{piece_type: PieceType::King, color: PieceColor::White}.to_unicode(), '♔');
+        assert_eq!(Piece{piece_type: PieceType::Pawn, color: PieceColor::Black}.to_unicode(), '♟');
+        assert_eq!(Piece{piece_type: PieceType::Knight, color: PieceColor::Black}.to_unicode(), '♞');
+        assert_eq!(Piece{piece_type: PieceType::Rook, color: PieceColor::White}.to_unicode(), '♖');
+    }
 }
\ No newline at end of file
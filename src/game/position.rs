@@ -1,8 +1,9 @@
 use super::piece::PieceColor;
 use std::fmt;
-use eyre::{eyre, Result};
+use eyre::{eyre, Report, Result};
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+// Ordered by row then column, the same as the square index a1 = 0 through h8 = 63
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
 pub struct Position {
     row: usize,
     column: usize,
@@ -27,6 +28,27 @@ impl fmt::Display for Position {
     }
 }
 
+impl TryFrom<(usize, usize)> for Position {
+    type Error = Report;
+
+    /// Builds a position from a (row, column) pair, both of which must be below 8
+    fn try_from((row, column): (usize, usize)) -> Result<Position> {
+        if row > 7 || column > 7 {
+            return Err(eyre!("Position ({}, {}) is off the board", row, column));
+        }
+
+        Ok(Position::encode(row, column))
+    }
+}
+
+impl TryFrom<&str> for Position {
+    type Error = Report;
+
+    fn try_from(loc: &str) -> Result<Position> {
+        Position::from_str(loc)
+    }
+}
+
 impl Position {
     pub fn encode(row: usize, column: usize) -> Position {
         Position {row, column}
@@ -49,6 +71,21 @@ impl Position {
         (self.row as isize, self.column as isize)
     }
 
+    /// Gets the square `d_row` rows and `d_col` columns away, or None when that is off the board
+    pub fn offset(&self, d_row: isize, d_col: isize) -> Option<Position> {
+        Position::encode_checked(self.row as isize + d_row, self.column as isize + d_col)
+    }
+
+    /// Numbers the squares a1 = 0, b1 = 1 through h8 = 63
+    pub fn index(&self) -> usize {
+        self.row * 8 + self.column
+    }
+
+    /// Gets the square numbered by index, or None for numbers of 64 and up
+    pub fn from_index(index: usize) -> Option<Position> {
+        (index < 64).then(|| Position::encode(index / 8, index % 8))
+    }
+
     pub fn row(&self) -> usize {
         self.row
     }
@@ -57,6 +94,16 @@ impl Position {
         self.column
     }
 
+    /// The color of the square itself, Black for dark squares such as a1
+    pub fn square_color(&self) -> PieceColor {
+        if (self.row + self.column).is_multiple_of(2) {
+            PieceColor::Black
+        }
+        else {
+            PieceColor::White
+        }
+    }
+
     pub fn from_str(loc: &str) -> Result<Position> {
         let bytes: Vec<char> = loc.chars().collect();
 
@@ -78,6 +125,46 @@ impl Position {
         Ok(Position::encode(row, col))
     }
 
+    /// Gets the rank counted from the given color's side, 0 being its back rank and 7 its promotion rank
+    pub fn relative_rank(&self, player_color: &PieceColor) -> usize {
+        match player_color {
+            PieceColor::Black => 7 - self.row,
+            PieceColor::White => self.row,
+        }
+    }
+
+    /// Gets the up to eight on-board squares a king could step to from this one
+    pub fn neighbors(&self) -> impl Iterator<Item = Position> {
+        let position = *self;
+
+        [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)].into_iter()
+            .filter_map(move |(row_step, column_step)| position.offset(row_step, column_step))
+    }
+
+    /// Gets the squares strictly between two positions on the same row, column or diagonal,
+    /// or nothing when they are not aligned
+    pub fn squares_between(&self, other: &Position) -> Vec<Position> {
+        let (row, column) = self.decode_isize();
+        let (other_row, other_column) = other.decode_isize();
+        let (row_step, column_step) = ((other_row - row).signum(), (other_column - column).signum());
+
+        let is_aligned = row == other_row || column == other_column || (other_row - row).abs() == (other_column - column).abs();
+        if !is_aligned || self == other {
+            return vec!();
+        }
+
+        let mut squares = vec!();
+        let (mut search_row, mut search_column) = (row + row_step, column + column_step);
+
+        while (search_row, search_column) != (other_row, other_column) {
+            squares.push(Position::encode(search_row as usize, search_column as usize));
+            search_row += row_step;
+            search_column += column_step;
+        }
+
+        squares
+    }
+
     pub fn forward_checked(&self, player_color: &PieceColor) -> Option<Position> {
         match player_color {
             PieceColor::Black => if self.row != 0 {
@@ -118,4 +205,77 @@ impl Position {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset() {
+        let d4 = Position::from_str("d4").expect("");
+        assert!(d4.offset(1, 2) == Position::from_str("f5").ok());
+        assert!(d4.offset(-3, -3) == Position::from_str("a1").ok());
+        assert!(d4.offset(0, 0) == Some(d4));
+
+        let h8 = Position::from_str("h8").expect("");
+        assert!(h8.offset(1, 0).is_none() && h8.offset(0, 1).is_none() && h8.offset(-8, 0).is_none());
+        assert!(Position::from_str("a1").expect("").offset(-1, 1).is_none());
+    }
+
+    #[test]
+    fn test_index_round_trip() {
+        for index in 0..64 {
+            assert!(Position::from_index(index).expect("").index() == index);
+        }
+
+        assert!(Position::from_str("a1").expect("").index() == 0);
+        assert!(Position::from_str("e4").expect("").index() == 28);
+        assert!(Position::from_str("h8").expect("").index() == 63);
+        assert!(Position::from_index(64).is_none());
+    }
+
+    #[test]
+    fn test_squares_between() {
+        let between = |from: &str, to: &str| -> Vec<String> {
+            Position::from_str(from).expect("").squares_between(&Position::from_str(to).expect("")).iter().map(|position| position.to_string()).collect()
+        };
+
+        assert_eq!(between("a1", "d4"), vec!["b2", "c3"]);
+        assert_eq!(between("e8", "e5"), vec!["e7", "e6"]);
+        assert!(between("a1", "b3").is_empty());
+        assert!(between("a1", "b2").is_empty());
+    }
+
+    #[test]
+    fn test_neighbors() {
+        assert!(Position::from_str("a1").expect("").neighbors().count() == 3);
+        assert!(Position::from_str("h5").expect("").neighbors().count() == 5);
+        assert!(Position::from_str("d4").expect("").neighbors().count() == 8);
+        assert!(Position::from_str("a1").expect("").neighbors().all(|position| ["a2", "b1", "b2"].contains(&position.to_string().as_str())));
+    }
+
+    #[test]
+    fn test_try_from() {
+        assert!(Position::try_from((8, 0)).is_err());
+        assert!(Position::try_from((0, 8)).is_err());
+        assert!(Position::try_from((7, 4)).is_ok_and(|position| position.to_string() == "e8"));
+        assert!(Position::try_from("c3").is_ok_and(|position| position == Position::encode(2, 2)));
+        assert!(Position::try_from("i9").is_err());
+    }
+
+    #[test]
+    fn test_square_color() {
+        assert!(Position::from_str("a1").expect("").square_color() == PieceColor::Black);
+        assert!(Position::from_str("h8").expect("").square_color() == PieceColor::Black);
+        assert!(Position::from_str("a8").expect("").square_color() == PieceColor::White);
+        assert!(Position::from_str("h1").expect("").square_color() == PieceColor::White);
+    }
+
+    #[test]
+    fn test_relative_rank() {
+        assert!(Position::from_str("e2").expect("").relative_rank(&PieceColor::White) == 1);
+        assert!(Position::from_str("e7").expect("").relative_rank(&PieceColor::Black) == 1);
+        assert!(Position::from_str("a8").expect("").relative_rank(&PieceColor::White) == 7);
+        assert!(Position::from_str("a8").expect("").relative_rank(&PieceColor::Black) == 0);
+    }
+}
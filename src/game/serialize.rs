@@ -0,0 +1,122 @@
+//! Serde support for the game types, behind the `serde` feature. The enums derive it, the
+//! rest are written in the same text forms the crate already reads and writes
+
+use super::{board::Board, piece::Piece, position::Position, Game};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// A piece is its FEN letter, uppercase for white
+impl Serialize for Piece {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_char(self.to_char())
+    }
+}
+
+impl<'de> Deserialize<'de> for Piece {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Piece, D::Error> {
+        let name = char::deserialize(deserializer)?;
+        Piece::get_piece(name).ok_or_else(|| de::Error::custom(format!("Invalid piece {}", name)))
+    }
+}
+
+/// A position is its square name such as e4
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Position {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Position, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Position::from_str(&name).map_err(de::Error::custom)
+    }
+}
+
+/// A board is its 64 squares from a1 to h8, rank by rank, with empty squares as null
+impl Serialize for Board {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq((0..64).map(|index| self.get(&Position::encode(index / 8, index % 8))))
+    }
+}
+
+impl<'de> Deserialize<'de> for Board {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Board, D::Error> {
+        let squares = Vec::<Option<Piece>>::deserialize(deserializer)?;
+        if squares.len() != 64 {
+            return Err(de::Error::invalid_length(squares.len(), &"64 squares"));
+        }
+
+        let mut board = Board::default();
+        for (index, square) in squares.into_iter().enumerate() {
+            if let Some(piece) = square {
+                board.add_piece(piece, &Position::encode(index / 8, index % 8));
+            }
+        }
+
+        Ok(board)
+    }
+}
+
+/// A game is its FEN, so the repetition history starts over when it's read back
+impl Serialize for Game {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_fen())
+    }
+}
+
+impl<'de> Deserialize<'de> for Game {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Game, D::Error> {
+        let fen = String::deserialize(deserializer)?;
+        Game::from_fen(&fen).map_err(|error| de::Error::custom(format!("Invalid FEN \"{}\": {}", fen, error)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{chess_move::ChessMove, piece::{PieceColor, PieceType}};
+
+    #[test]
+    fn test_game_round_trip() {
+        let game = Game::new();
+        let json = serde_json::to_string(&game).expect("");
+        assert!(json == "\"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1\"");
+        assert!(serde_json::from_str::<Game>(&json).expect("").to_fen() == game.to_fen());
+
+        let mut game = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").expect("");
+        game.play_line("a2a4").expect("");
+        let json = serde_json::to_string(&game).expect("");
+        assert!(serde_json::from_str::<Game>(&json).expect("").to_fen() == game.to_fen());
+
+        assert!(serde_json::from_str::<Game>("\"8/8/8 w - - 0 1\"").is_err());
+    }
+
+    #[test]
+    fn test_board_round_trip() {
+        let board = Game::new().board;
+        let json = serde_json::to_string(&board).expect("");
+        assert!(json.starts_with("[\"R\",\"N\",\"B\",\"Q\",\"K\",\"B\",\"N\",\"R\",\"P\","));
+        assert!(json.matches("null").count() == 32);
+
+        let read_board = serde_json::from_str::<Board>(&json).expect("");
+        assert!(read_board == board);
+        assert!(read_board.get_king(&PieceColor::Black) == Some(Position::from_str("e8").expect("")));
+
+        assert!(serde_json::from_str::<Board>("[null, \"K\"]").is_err());
+    }
+
+    #[test]
+    fn test_move_and_piece_round_trip() {
+        let chess_move = ChessMove::PawnPromote(Position::from_str("b7").expect(""), Position::from_str("a8").expect(""), PieceType::Bishup);
+        let json = serde_json::to_string(&chess_move).expect("");
+        assert!(json == "{\"PawnPromote\":[\"b7\",\"a8\",\"Bishop\"]}");
+        assert!(serde_json::from_str::<ChessMove>(&json).expect("") == chess_move);
+        assert!(serde_json::from_str::<ChessMove>("\"CastleKingside\"").expect("") == ChessMove::CastleKingside);
+
+        let piece = Piece{piece_type: PieceType::Knight, color: PieceColor::Black};
+        assert!(serde_json::to_string(&piece).expect("") == "\"n\"");
+        assert!(serde_json::from_str::<Piece>("\"n\"").expect("") == piece);
+        assert!(serde_json::from_str::<Piece>("\"x\"").is_err());
+        assert!(serde_json::from_str::<Position>("\"i9\"").is_err());
+    }
+}
@@ -0,0 +1,28 @@
+// Board, Game and the move types have long exposed inherent default/from_str constructors
+#![allow(clippy::should_implement_trait, clippy::new_without_default)]
+
+pub mod game;
+pub mod engine;
+
+#[cfg(feature = "client")]
+pub mod client;
+
+/// The types most callers need, in one import.
+///
+/// ```
+/// use chessbot::prelude::*;
+///
+/// let mut game = Game::new();
+/// assert_eq!(game.get_moves().len(), 20);
+///
+/// game.make_move(&ChessMove::from_str("e2e4").unwrap());
+/// assert_eq!(game.turn, PieceColor::Black);
+/// ```
+pub mod prelude {
+    pub use crate::engine::Engine;
+    pub use crate::game::Game;
+    pub use crate::game::board::Board;
+    pub use crate::game::chess_move::ChessMove;
+    pub use crate::game::piece::{Piece, PieceColor, PieceType};
+    pub use crate::game::position::Position;
+}
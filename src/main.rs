@@ -1,99 +1,623 @@
-mod game;
-mod client;
-mod engine;
-
-use clap::Parser;
-use client::Client;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use chessbot::client::{Browser, Client, ClientConfig, GameEndReason, Shutdown, DEFAULT_WEBDRIVER_URL};
+use chessbot::client::lichess::LichessClient;
+use chessbot::engine::{DecisionThresholds, EvalParams, GameDecision};
+use chessbot::game::{GameResult, START_FEN};
+use chessbot::prelude::*;
+use eyre::{eyre, Result, WrapErr};
+use log::{debug, info, warn, LevelFilter, Log, Metadata, Record};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
 use tokio::time::{sleep, Duration};
-use game::{Game, chess_move::ChessMove, piece::PieceColor};
-use engine::Engine;
 
 #[derive(Parser)]
-struct Args {
-    phpsessid: String,
-    search_depth: u16,
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Most detailed log messages to print to stderr: off, error, warn, info, debug or trace
+    #[arg(long, global = true, default_value_t = LevelFilter::Info)]
+    log_level: LevelFilter,
+}
+
+/// Writes log records to stderr so they never mix with the moves printed on stdout
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}: {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+// How many times the chess.com client tries a move before giving up on the game
+const MAX_MOVE_ATTEMPTS: usize = 5;
+
+#[derive(Subcommand)]
+enum Command {
+    /// Play on chess.com through a locally running ChromeDriver, or on Lichess through its Board API
+    Play {
+        /// The chess.com PHPSESSID cookie, or a Lichess API token with the board:play scope
+        phpsessid: String,
+        search_depth: u16,
+        /// Site to play on
+        #[arg(long, value_enum, default_value_t = Backend::ChessCom)]
+        backend: Backend,
+        #[command(flatten)]
+        driver: DriverArgs,
+        /// On chess.com, prepare an answer to the opponent's likeliest reply while they think
+        #[arg(long)]
+        premove: bool,
+        #[command(flatten)]
+        eval: EvalArgs,
+        #[command(flatten)]
+        thresholds: ThresholdArgs,
+    },
+    /// Print the engine's preferred move for a position
+    Analyze {
+        #[command(flatten)]
+        position: PositionArgs,
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u16).range(2..))]
+        depth: u16,
+        #[command(flatten)]
+        eval: EvalArgs,
+    },
+    /// Let the engine play both sides until the game ends
+    Selfplay {
+        #[command(flatten)]
+        position: PositionArgs,
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u16).range(2..))]
+        depth: u16,
+        #[command(flatten)]
+        eval: EvalArgs,
+        /// Moves to play before the engine takes over, in SAN ("1. e4 e5") or coordinates ("e2e4 e7e5")
+        #[arg(long)]
+        opening: Option<String>,
+        /// Stop after this many half moves, counting the opening
+        #[arg(long)]
+        max_plies: Option<usize>,
+    },
+    /// Speak the Universal Chess Interface on stdin and stdout, for use from a chess GUI
+    Uci {
+        /// Search depth used when `go` doesn't give one
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u16).range(2..))]
+        depth: u16,
+        #[command(flatten)]
+        eval: EvalArgs,
+    },
+    /// Count the move tree's leaf nodes below each legal move
+    Perft {
+        #[command(flatten)]
+        position: PositionArgs,
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        depth: u64,
+    },
+}
+
+#[derive(Args)]
+struct PositionArgs {
+    /// Starting position in Forsyth-Edwards Notation
+    #[arg(long, default_value = START_FEN)]
+    fen: String,
+}
+
+#[derive(Args)]
+struct EvalArgs {
+    /// Set of material and positional weights the engine evaluates with
+    #[arg(long, value_enum, default_value_t = EvalPreset::Standard)]
+    eval_preset: EvalPreset,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Backend {
+    ChessCom,
+    Lichess,
+}
+
+#[derive(Args)]
+struct DriverArgs {
+    /// WebDriver server to play chess.com through, such as ChromeDriver, geckodriver or a Selenium grid
+    #[arg(long, default_value = DEFAULT_WEBDRIVER_URL)]
+    webdriver_url: String,
+    /// Browser for the WebDriver server to open
+    #[arg(long, value_enum, default_value_t = BrowserChoice::Chrome)]
+    browser: BrowserChoice,
+    /// Run the browser without a window
+    #[arg(long)]
+    headless: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BrowserChoice {
+    Chrome,
+    Firefox,
+}
+
+impl DriverArgs {
+    fn config(&self) -> ClientConfig {
+        ClientConfig {
+            webdriver_url: self.webdriver_url.clone(),
+            browser: match self.browser {
+                BrowserChoice::Chrome => Browser::Chrome,
+                BrowserChoice::Firefox => Browser::Firefox,
+            },
+            headless: self.headless,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EvalPreset {
+    Standard,
+    Aggressive,
+    KingHunt,
+}
+
+#[derive(Args)]
+struct ThresholdArgs {
+    /// Stop and resign once the engine's score stays below this many centipawns
+    #[arg(long, value_name = "CP", allow_hyphen_values = true)]
+    resign_threshold: Option<i32>,
+    /// Claim or accept a draw once the engine's score stays below this many centipawns
+    #[arg(long, value_name = "CP", allow_hyphen_values = true)]
+    draw_threshold: Option<i32>,
+    /// How many of the engine's moves in a row have to score past a threshold
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..))]
+    threshold_moves: u64,
+}
+
+impl ThresholdArgs {
+    fn thresholds(&self) -> DecisionThresholds {
+        DecisionThresholds {
+            resign: self.resign_threshold,
+            draw: self.draw_threshold,
+            moves: self.threshold_moves as usize,
+        }
+    }
+}
+
+impl EvalArgs {
+    fn params(&self) -> EvalParams {
+        match self.eval_preset {
+            EvalPreset::Standard => EvalParams::standard(),
+            EvalPreset::Aggressive => EvalParams::aggressive(),
+            EvalPreset::KingHunt => EvalParams::king_hunt(),
+        }
+    }
+}
+
+impl PositionArgs {
+    fn game(&self) -> Result<Game> {
+        Game::from_fen(&self.fen).wrap_err_with(|| format!("Could not parse FEN \"{}\"", self.fen))
+    }
 }
 
 #[tokio::main]
-async fn main() {
-    let args = Args::parse();
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    log::set_logger(&LOGGER).wrap_err("Could not install logger")?;
+    log::set_max_level(cli.log_level);
+
+    match cli.command {
+        Command::Play { phpsessid, search_depth, backend: Backend::ChessCom, driver, premove, eval, thresholds } => play(phpsessid, driver.config(), search_depth, premove, eval.params(), thresholds.thresholds()).await,
+        Command::Play { phpsessid, search_depth, backend: Backend::Lichess, driver: _, premove: _, eval, thresholds } => play_lichess(phpsessid, search_depth, eval.params(), thresholds.thresholds()).await,
+        Command::Analyze { position, depth, eval } => analyze(position.game()?, depth, eval.params()),
+        Command::Selfplay { position, depth, eval, opening, max_plies } => selfplay(position.game()?, depth, eval.params(), opening.as_deref(), max_plies)?,
+        Command::Uci { depth, eval } => uci(depth, eval.params())?,
+        Command::Perft { position, depth } => perft(position.game()?, depth as usize),
+    }
+
+    Ok(())
+}
+
+async fn play(phpsessid: String, config: ClientConfig, search_depth: u16, premove: bool, eval_params: EvalParams, thresholds: DecisionThresholds) {
+    let shutdown = Shutdown::new();
+    let handler = shutdown.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            handler.request();
+        }
+    });
+
+    // Read stdin on its own thread so a blocked read doesn't keep Ctrl-C from ending the loop
+    let (enter_sender, enter_receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        while std::io::stdin().read_line(&mut String::new()).is_ok_and(|read| read > 0) {
+            if enter_sender.send(()).is_err() {
+                break;
+            }
+        }
+    });
+
     println!("Connecting to Chess.com");
-    let mut client = Client::new(args.phpsessid).await.unwrap();
+    let mut client = Client::with_config(phpsessid, &config).await.unwrap();
 
     loop {
         println!("Connected to Browser, Press Enter to Continue");
-        let _ = std::io::stdin().read_line(&mut String::new()).unwrap();
+        if !wait_for_enter(&enter_receiver, &shutdown).await {
+            break;
+        }
         println!("Playing");
-        run_client(&mut client, args.search_depth).await;
-        println!("Game Over!");
+        run_client(&mut client, search_depth, premove, eval_params, thresholds, &shutdown).await;
+        if shutdown.is_requested() {
+            break;
+        }
+        match client.get_game_result().await {
+            Ok(Some(reason)) => println!("Game Over! {}", reason),
+            _ => println!("Game Over!"),
+        }
+    }
+
+    println!("Shutting down");
+    if client.disconnect().await.is_err() {
+        println!("Could not close the browser session");
+    }
+}
+
+async fn play_lichess(token: String, search_depth: u16, eval_params: EvalParams, thresholds: DecisionThresholds) {
+    println!("Waiting for a Lichess game to start");
+
+    // Nothing needs closing on the way out, so Ctrl-C can drop the game wherever it is
+    tokio::select! {
+        result = run_lichess(token, search_depth, eval_params, thresholds) => match result {
+            Ok(Some(reason)) => println!("Game Over! {}", reason),
+            Ok(None) => println!("Game Over!"),
+            Err(error) => println!("Lichess game failed: {:#}", error),
+        },
+        _ = tokio::signal::ctrl_c() => (),
+    }
+
+    println!("Shutting down");
+}
+
+/// Waits for the enter key, returning false if a shutdown was requested first
+async fn wait_for_enter(enter_receiver: &Receiver<()>, shutdown: &Shutdown) -> bool {
+    while !shutdown.is_requested() {
+        match enter_receiver.try_recv() {
+            Ok(()) => return true,
+            Err(TryRecvError::Empty) => sleep(Duration::from_millis(100)).await,
+            Err(TryRecvError::Disconnected) => return false,
+        }
+    }
+
+    false
+}
+
+fn analyze(game: Game, depth: u16, eval_params: EvalParams) {
+    let engine = Engine::with_eval_params(game.clone(), game.turn, depth, eval_params);
+
+    match engine.get_best_move_parallel() {
+        Some(chess_move) => println!("{}", chess_move),
+        None => println!("{:?}", game.status()),
+    }
+}
+
+fn selfplay(mut game: Game, depth: u16, eval_params: EvalParams, opening: Option<&str>, max_plies: Option<usize>) -> Result<()> {
+    let mut plies = 0;
+
+    if let Some(opening) = opening {
+        for chess_move in game.play_line(opening).wrap_err("Could not play the opening")? {
+            println!("{}", chess_move);
+            plies += 1;
+        }
+    }
+
+    while game.status() == GameResult::Ongoing && max_plies.is_none_or(|max_plies| plies < max_plies) {
+        let engine = Engine::with_eval_params(game.clone(), game.turn, depth, eval_params);
+        let chess_move = match engine.get_best_move_parallel() {
+            Some(chess_move) => chess_move,
+            None => break,
+        };
+
+        println!("{}", chess_move);
+        game.make_move(&chess_move);
+        plies += 1;
+    }
+
+    println!("{:?}", game.status());
+    Ok(())
+}
+
+fn uci(default_depth: u16, eval_params: EvalParams) -> Result<()> {
+    let mut game = Game::new();
+
+    for line in std::io::stdin().lines() {
+        let line = line.wrap_err("Could not read from stdin")?;
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("uci") => {
+                println!("id name {} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+                println!("id author {}", env!("CARGO_PKG_AUTHORS"));
+                println!("uciok");
+            }
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => game = Game::new(),
+            Some("position") => match uci_position(tokens.collect()) {
+                Ok(position) => game = position,
+                Err(error) => warn!("Ignoring position command: {:#}", error),
+            },
+            Some("go") => {
+                // Only a fixed depth is supported, clock and node limits are ignored
+                let mut depth = default_depth;
+                while let Some(token) = tokens.next() {
+                    if token == "depth" {
+                        depth = tokens.next().and_then(|depth| depth.parse().ok()).unwrap_or(default_depth).max(2);
+                    }
+                }
+
+                let engine = Engine::with_eval_params(game.clone(), game.turn, depth, eval_params);
+                match engine.get_best_move_parallel() {
+                    Some(chess_move) => println!("bestmove {}", chess_move.to_uci(&game.turn)),
+                    None => println!("bestmove 0000"),
+                }
+            }
+            Some("quit") => break,
+            Some(command) => warn!("Unknown UCI command {}", command),
+            None => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the game for a UCI position command, given the tokens after "position"
+fn uci_position(tokens: Vec<&str>) -> Result<Game> {
+    let moves_index = tokens.iter().position(|&token| token == "moves").unwrap_or(tokens.len());
+
+    let mut game = match tokens.first() {
+        Some(&"startpos") => Game::new(),
+        Some(&"fen") => Game::from_fen(&tokens[1..moves_index].join(" ")).wrap_err("Could not parse FEN")?,
+        _ => return Err(eyre!("Expected startpos or fen")),
+    };
+
+    for token in tokens.iter().skip(moves_index + 1) {
+        match game.parse_move(token).filter(|chess_move| game.get_moves().contains(chess_move)) {
+            Some(chess_move) => game.make_move(&chess_move),
+            None => return Err(eyre!("Move {} is not legal in {}", token, game.to_fen())),
+        }
     }
+
+    Ok(game)
 }
 
-async fn run_client(client: &mut Client, search_depth: u16) {
+fn perft(game: Game, depth: usize) {
+    let mut total = 0;
+
+    for (chess_move, count) in game.perft_divide(depth) {
+        println!("{}: {}", chess_move.to_uci(&game.turn), count);
+        total += count;
+    }
+
+    println!();
+    println!("Nodes searched: {}", total);
+}
+
+async fn run_client(client: &mut Client, search_depth: u16, premove: bool, eval_params: EvalParams, thresholds: DecisionThresholds, shutdown: &Shutdown) {
     let player_color = client.get_player_color().await.expect("Error! Could not get player color");
-    let mut engine = Engine::new(Game::new(), player_color, search_depth);
+    let mut engine = Engine::with_eval_params(Game::new(), player_color, search_depth, eval_params);
     client.update_pieces_from_board(&engine.game.board);
 
-    let mut is_my_turn = player_color == PieceColor::White;
     let mut keep_playing = true;
+    let mut scores = vec!();
 
-    while keep_playing {
-        keep_playing = if is_my_turn {
-            is_my_turn = !is_my_turn;
-            pick_and_make_move(client, &mut engine).await
+    // A premove plays two plies at once, so whose turn it is comes from the game rather than alternating
+    while keep_playing && !shutdown.is_requested() {
+        keep_playing = if engine.game.turn == player_color {
+            pick_and_make_move(client, &mut engine, &thresholds, &mut scores, shutdown).await
         }
         else {
-            is_my_turn = !is_my_turn;
             // TODO: Fix issue with getting bad moves
-            wait_for_opponent_move(client, &mut engine).await
+            wait_for_opponent_move(client, &mut engine, premove.then_some(search_depth), shutdown).await
         }
     }
 }
 
-async fn pick_and_make_move(client: &mut Client, engine: &mut Engine) -> bool {
-    if let Some(chess_move) = engine.get_best_move_parallel() {
-        println!("{}", chess_move);
-        while client.make_move(&chess_move, &engine.player).await.is_err() {
-            println!("Client failed to make move")
+async fn run_lichess(token: String, search_depth: u16, eval_params: EvalParams, thresholds: DecisionThresholds) -> Result<Option<GameEndReason>> {
+    let mut client = LichessClient::new(token).await?;
+    let player_color = client.get_player_color().await?;
+    // The game may not start from the standard position or may already be under way
+    let mut engine = Engine::with_eval_params(client.game().clone(), player_color, search_depth, eval_params);
+    let mut scores = vec!();
+    println!("Playing");
+
+    while !client.is_game_over() {
+        if engine.game.turn == player_color {
+            let budget = client.get_remaining_time(&player_color).await.ok().map(move_budget);
+            let Some(chess_move) = choose_move(&mut engine, budget, &thresholds, &mut scores) else {
+                break;
+            };
+
+            info!("{}", chess_move);
+            client.make_move(&chess_move, &player_color).await?;
+            engine.advance_move(chess_move);
+        }
+        else {
+            let Some(opponent_move) = client.get_opponent_move(&!player_color).await? else {
+                break;
+            };
+
+            info!("{}", opponent_move);
+            engine.advance_move(opponent_move);
         }
-        engine.advance_move(chess_move);
-        client.update_pieces_from_board(&engine.game.board);
     }
-    else
-    {
+
+    Ok(client.get_game_result())
+}
+
+async fn pick_and_make_move(client: &mut Client, engine: &mut Engine, thresholds: &DecisionThresholds, scores: &mut Vec<i32>, shutdown: &Shutdown) -> bool {
+    // Untimed games have no clock to read and keep the fixed depth search
+    let budget = client.get_remaining_time(&engine.player).await.ok().map(move_budget);
+    let Some(chess_move) = choose_move(engine, budget, thresholds, scores) else {
         return false;
+    };
+
+    info!("{}", chess_move);
+    let expected = engine.game.peek_move(&chess_move);
+    let mut attempts = 1;
+    while let Err(error) = client.make_move(&chess_move, &engine.player, &expected).await {
+        warn!("Client failed to make move: {}", error);
+
+        // The move may have landed after the check gave up, in which case clicking again would only fail
+        if client.has_move_landed(&expected).await.unwrap_or(false) {
+            break;
+        }
+        if attempts >= MAX_MOVE_ATTEMPTS || shutdown.is_requested() {
+            warn!("Giving up on {} after {} attempts", chess_move, attempts);
+            return false;
+        }
+
+        attempts += 1;
+        client.update_pieces_from_board(&engine.game.board);
     }
+    engine.advance_move(chess_move);
+    client.update_pieces_from_board(&engine.game.board);
 
     true
 }
 
-async fn wait_for_opponent_move(client: &mut Client, engine: &mut Engine) -> bool {
+/// How long to think about a move with `remaining` left on the clock, spreading it over
+/// the moves a game usually still has to go
+fn move_budget(remaining: Duration) -> Duration {
+    remaining / 30
+}
+
+/// Searches for the engine's move, within `budget` when the game is timed, or None when it
+/// has none or its score says to stop playing
+fn choose_move(engine: &mut Engine, budget: Option<Duration>, thresholds: &DecisionThresholds, scores: &mut Vec<i32>) -> Option<ChessMove> {
+    let (chess_move, score) = match budget {
+        Some(budget) => {
+            debug!("Searching for {:?}", budget);
+            engine.get_best_move_timed_with_score(budget)?
+        },
+        None => engine.get_best_move_parallel_with_score()?,
+    };
+    scores.push(score);
+
+    // The clients can't press the resign or draw buttons so the bot stops and leaves that to the user
+    match thresholds.decide(scores) {
+        GameDecision::Resign => {
+            info!("Score {} has stayed below the resign threshold, resign in the browser", score);
+            return None;
+        }
+        GameDecision::AcceptDraw if matches!(engine.game.status(), GameResult::FiftyMoveClaimable | GameResult::ThreefoldClaimable) => {
+            info!("Score {} has stayed below the draw threshold, claim the {:?} draw in the browser", score, engine.game.status());
+            return None;
+        }
+        GameDecision::AcceptDraw => info!("Score {} has stayed below the draw threshold, a draw offer can be accepted", score),
+        GameDecision::Continue => (),
+    }
+
+    Some(chess_move)
+}
+
+/// Our answer to the opponent's likeliest reply, searched on a blocking thread while they
+/// think so the bot keeps watching the board and Ctrl-C in the meantime
+struct PremoveSearch {
+    task: tokio::task::JoinHandle<Option<(ChessMove, Game, ChessMove)>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl PremoveSearch {
+    fn start(engine: &Engine, search_depth: u16) -> PremoveSearch {
+        let mut predictor = Engine::with_eval_params(engine.game.clone(), engine.player, search_depth, engine.eval_params);
+        let stop = predictor.stop_flag();
+
+        let task = tokio::task::spawn_blocking(move || {
+            let is_stopped = |predictor: &Engine| predictor.stop_flag().load(Ordering::Relaxed);
+
+            let &(reply, _) = predictor.get_top_moves(1).first()?;
+            if is_stopped(&predictor) {
+                return None;
+            }
+
+            predictor.advance_move(reply);
+            let response = predictor.get_best_move_parallel()?;
+            (!is_stopped(&predictor)).then(|| (reply, predictor.game.clone(), response))
+        });
+
+        PremoveSearch{task, stop}
+    }
+
+    /// Stops the search, whose answer is no use once the opponent has moved
+    fn cancel(self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Waits for the opponent's move and advances the engine with it. With `premove_depth`,
+/// our answer to their likeliest reply is searched that deep meanwhile and played at once
+/// if they make it
+async fn wait_for_opponent_move(client: &mut Client, engine: &mut Engine, premove_depth: Option<u16>, shutdown: &Shutdown) -> bool {
     let mut opponent_move: Option<ChessMove> = None;
-    let valid_moves = engine.game.get_moves();
 
-    if valid_moves.is_empty() {
+    if engine.legal_moves().is_empty() {
         return false;
     }
 
+    let mut premove_search = premove_depth.map(|depth| PremoveSearch::start(engine, depth));
+
     while !client.board_has_changed().await {
         // Wait for board to change
+        if shutdown.is_requested() {
+            if let Some(search) = premove_search {
+                search.cancel();
+            }
+            return false;
+        }
+
+        // The premove is only staged if its search finishes before the opponent moves
+        match premove_search.take() {
+            Some(search) if search.task.is_finished() => {
+                if let Ok(Some((reply, game_after_reply, response))) = search.task.await {
+                    debug!("Expecting {}, premoving {}", reply, response);
+                    client.make_premove(reply, game_after_reply, response);
+                }
+            }
+            unfinished => premove_search = unfinished,
+        }
+    }
+
+    if let Some(search) = premove_search {
+        debug!("Opponent moved before the premove was ready");
+        search.cancel();
+    }
+
+    // The staged premove goes in before the page settles, the wait below being for a move to read
+    match client.play_premove(&engine.player).await {
+        Ok(Some((reply, response))) => {
+            info!("{}", reply);
+            engine.advance_move(reply);
+            info!("{} (premove)", response);
+            engine.advance_move(response);
+            client.update_pieces_from_board(&engine.game.board);
+            return true;
+        }
+        Ok(None) => (),
+        Err(error) => warn!("Client failed to make premove: {}", error),
     }
 
     sleep(Duration::from_secs(1)).await;
 
-    while opponent_move.is_none() {
+    while opponent_move.is_none() && !shutdown.is_requested() {
         opponent_move = client.get_opponent_move(&!engine.player).await.ok().flatten();
-        if engine.game.get_moves().is_empty() {
-            return false;
-        }
 
         if let Some(o_move) = opponent_move {
-            if valid_moves.iter().any(|valid_move| &o_move == valid_move) {
-                println!("{}", o_move);
+            if engine.legal_moves().contains(&o_move) {
+                info!("{}", o_move);
                 engine.advance_move(o_move);
             }
             else {
-                println!("Bad move recognized {}! Trying again.", o_move);
+                warn!("Bad move recognized {}! Trying again.", o_move);
                 opponent_move = None;
                 client.update_pieces_from_board(&engine.game.board);
             }
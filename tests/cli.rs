@@ -0,0 +1,86 @@
+#![cfg(feature = "client")]
+
+use chessbot::prelude::*;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn test_selfplay_mate_in_one() {
+    let output = Command::new(env!("CARGO_BIN_EXE_chessbot"))
+        .args(["selfplay", "--fen", "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "--depth", "3"])
+        .output()
+        .expect("Could not run chessbot");
+
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("Output was not utf8");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines, vec!["a1a8", "Checkmate(Black)"]);
+}
+
+#[test]
+fn test_invalid_fen_is_reported() {
+    let output = Command::new(env!("CARGO_BIN_EXE_chessbot"))
+        .args(["analyze", "--fen", "not a fen"])
+        .output()
+        .expect("Could not run chessbot");
+
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).expect("Output was not utf8");
+    assert!(stderr.contains("Could not parse FEN"));
+}
+
+#[test]
+fn test_quiet_analyze_prints_only_the_move() {
+    let output = Command::new(env!("CARGO_BIN_EXE_chessbot"))
+        .args(["analyze", "--fen", "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "--depth", "3", "--log-level", "off"])
+        .output()
+        .expect("Could not run chessbot");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).expect("Output was not utf8"), "a1a8\n");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_selfplay_from_opening() {
+    let output = Command::new(env!("CARGO_BIN_EXE_chessbot"))
+        .args(["selfplay", "--opening", "1. e4 e5", "--depth", "2", "--max-plies", "4"])
+        .output()
+        .expect("Could not run chessbot");
+
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("Output was not utf8");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[..2], ["e2e4", "e7e5"]);
+    assert_eq!(lines[4], "Ongoing");
+}
+
+#[test]
+fn test_uci_bestmove() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chessbot"))
+        .args(["uci"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Could not run chessbot");
+
+    child.stdin.take().expect("").write_all(b"uci\nisready\nposition startpos moves e2e4\ngo depth 2\nposition fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\ngo depth 3\nquit\n").expect("Could not write to chessbot");
+    let output = child.wait_with_output().expect("Could not run chessbot");
+
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("Output was not utf8");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[2..4], ["uciok", "readyok"]);
+
+    let mut game = Game::new();
+    game.make_move(&ChessMove::from_str("e2e4").expect(""));
+    let reply = lines[4].strip_prefix("bestmove ").and_then(|chess_move| game.parse_move(chess_move)).expect("No best move");
+    assert!(game.get_moves().contains(&reply));
+    assert_eq!(lines[5], "bestmove a1a8");
+}
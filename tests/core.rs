@@ -0,0 +1,47 @@
+use chessbot::game::{GameResult, START_FEN};
+use chessbot::prelude::*;
+
+#[test]
+fn test_game_without_client() {
+    let mut game = Game::new();
+    assert!(game.get_moves().len() == 20);
+
+    game.make_move(&ChessMove::from_str("e2e4").expect(""));
+    assert!(game.get_moves().len() == 20);
+    assert!(game.status() == GameResult::Ongoing);
+}
+
+#[test]
+fn test_engine_without_client() {
+    let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").expect("");
+    let engine = Engine::new(game.clone(), game.turn, 3);
+
+    assert!(engine.get_best_move_parallel() == ChessMove::from_str("a1a8"));
+}
+
+#[test]
+fn test_try_from_conversions() -> eyre::Result<()> {
+    let chess_move = ChessMove::try_from("e7e8q")?;
+    assert!(chess_move == ChessMove::PawnPromote(Position::try_from("e7")?, Position::try_from((7, 4))?, PieceType::Queen));
+    assert!(ChessMove::try_from("e9e8").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_start_fen() {
+    assert!(Game::new() == Game::from_fen(START_FEN).expect(""));
+}
+
+#[test]
+fn test_to_fen_round_trip() {
+    let mut game = Game::from_fen(START_FEN).expect("");
+    assert!(game.to_fen() == START_FEN);
+
+    game.make_move(&ChessMove::from_str("e2e3").expect(""));
+    assert!(game.to_fen() == "rnbqkbnr/pppppppp/8/8/8/4P3/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+
+    game.make_move(&ChessMove::from_str("d7d5").expect(""));
+    assert!(game.to_fen() == "rnbqkbnr/ppp1pppp/8/3p4/8/4P3/PPPP1PPP/RNBQKBNR w KQkq d6 0 2");
+    assert!(Game::from_fen(&game.to_fen()).expect("").to_fen() == game.to_fen());
+}